@@ -0,0 +1,138 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use anyhow::Result;
+use scip_talk::{PairingProblem, Participant, splitmix64};
+
+/// Compares solving with and without the rounding heuristic on generated rosters.
+///
+/// Usage: `benchmark [SIZE ...] [--runs K] [--time-limit SECONDS]`. For each size (default 20,
+/// 40 and 80 participants), generates `K` rosters (default 3) with 1 to 5 cards each, households
+/// of two or three and a few interests per person, then solves each one both ways.
+pub fn main() -> Result<()> {
+    let mut sizes = Vec::new();
+    let mut runs = 3;
+    let mut time_limit = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" => runs = parse_value(args.next(), &arg)?,
+            "--time-limit" => time_limit = Some(parse_value(args.next(), &arg)?),
+            size => sizes.push(
+                size.parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid roster size: {}", size))?,
+            ),
+        }
+    }
+    if runs == 0 {
+        anyhow::bail!("--runs must be at least 1");
+    }
+    if sizes.is_empty() {
+        sizes = vec![20, 40, 80];
+    }
+
+    println!(
+        "{:>5} {:>4} {:>9} {:>9} {:>9} {:>8} {:>12}  status",
+        "size", "run", "rounding", "time (s)", "best (s)", "nodes", "objective"
+    );
+    for &size in &sizes {
+        let mut totals = [0.; 2];
+        for run in 0..runs {
+            let seed = (size as u64) << 32 | run as u64;
+            for (k, rounding) in [false, true].into_iter().enumerate() {
+                let mut problem = generate_roster(size, seed);
+                if let Some(seconds) = time_limit {
+                    problem = problem.time_limit(seconds);
+                }
+                if rounding {
+                    problem = problem.rounding_heuristic();
+                }
+
+                // When the final pairing was found, as opposed to proven optimal.
+                let found_at = Rc::new(Cell::new(0.));
+                let found = found_at.clone();
+                problem = problem.on_progress(move |progress| found.set(progress.elapsed));
+
+                let solution = problem.solve()?;
+                let stats = &solution.stats;
+                totals[k] += stats.solving_time;
+                println!(
+                    "{:>5} {:>4} {:>9} {:>9.2} {:>9.2} {:>8} {:>12.3}  {:?}",
+                    size,
+                    run + 1,
+                    if rounding { "on" } else { "off" },
+                    stats.solving_time,
+                    found_at.get(),
+                    stats.n_nodes,
+                    stats.objective,
+                    stats.status
+                );
+            }
+        }
+        println!(
+            "{:>5} mean time: {:.2}s without rounding, {:.2}s with ({:+.0}%)",
+            size,
+            totals[0] / runs as f64,
+            totals[1] / runs as f64,
+            (totals[1] / totals[0] - 1.) * 100.
+        );
+    }
+    Ok(())
+}
+
+/// A random roster of `size` people, the same for the same `seed`.
+fn generate_roster(size: usize, seed: u64) -> PairingProblem {
+    const INTERESTS: [&str; 8] = [
+        "hiking",
+        "cats",
+        "baking",
+        "music",
+        "travel",
+        "art",
+        "books",
+        "gardening",
+    ];
+    let mut random = SplitMix64(seed);
+    let mut participants = Vec::new();
+    let mut household = 0;
+    while participants.len() < size {
+        let members = 1 + (random.next() % 3) as usize;
+        for _ in 0..members.min(size - participants.len()) {
+            let interests = INTERESTS
+                .iter()
+                .filter(|_| random.next().is_multiple_of(3))
+                .map(|interest| interest.to_string())
+                .collect();
+            let mut participant = Participant::new(
+                format!("P{}", participants.len() + 1),
+                1 + (random.next() % 5) as u32,
+            )
+            .interests(interests);
+            if members > 1 {
+                participant = participant.group(format!("H{}", household));
+            }
+            participants.push(participant);
+        }
+        household += 1;
+    }
+    PairingProblem::from_participants(participants)
+}
+
+/// A small, seedable random number generator, so rosters are reproducible without extra
+/// dependencies.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        splitmix64(self.0)
+    }
+}
+
+/// Parses the value following `flag` on the command line.
+fn parse_value<T: std::str::FromStr>(value: Option<String>, flag: &str) -> Result<T> {
+    let value = value.ok_or_else(|| anyhow::anyhow!("Missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid value for {}: {}", flag, value))
+}
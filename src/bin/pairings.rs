@@ -1,263 +1,1009 @@
+use std::str::FromStr;
+
 use anyhow::Result;
-use russcip::{Model, ObjSense, ProblemOrSolving, Status, VarType, WithSolutions};
-use image::{ImageBuffer, Rgb, RgbImage};
+use russcip::Status;
+use scip_talk::{
+    CardRange, CoveragePriority, DEFAULT_REPEAT_DECAY, DEFAULT_REPEAT_PENALTY, HistoryMode,
+    InternationalMix, MinCards, Objective, ObjectiveMode, PairingProblem, PairingSolution,
+    Participant, Progress, Role, ShippingCosts, SolveLimits, SolveStats, interrupt_on_ctrl_c,
+    load_history, load_pairings, load_preferences, load_roster, load_shipping_costs, save_pairings,
+    visualize_solution_matrix,
+};
+
+/// Command line options. Participants come either from `--roster FILE` or from shorthand card
+/// counts (see [`parse_shorthand_args`]).
+#[derive(Default)]
+struct Options {
+    roster: Option<String>,
+    /// `(send, receive)` card counts.
+    card_counts: Vec<(u32, u32)>,
+    /// `--exclude NAME,NAME,...`: people who must not exchange with each other.
+    exclusion_groups: Vec<Vec<String>>,
+    /// `--forbid SENDER:RECEIVER`: a single pair that must not be used.
+    forbidden_pairs: Vec<(String, String)>,
+    /// `--require SENDER:RECEIVER`: a pair that must be used.
+    required_pairs: Vec<(String, String)>,
+    /// `--history FILE`: pairings files from previous exchanges, oldest first.
+    history: Vec<String>,
+    /// `--history-mode forbid|penalize`, with `--repeat-penalty` and `--repeat-decay`. Repeats
+    /// are forbidden unless asked otherwise.
+    history_mode: HistoryMode,
+    /// `--min-cards N` or `--min-cards P%`: the fewest cards anyone should get, unless their
+    /// roster entry says otherwise.
+    min_cards: Option<MinCards>,
+    /// `--objective total|fairness|LEVEL,LEVEL,...`: what the solver optimizes. Levels are
+    /// objective names in priority order, each optionally with a `:TOLERANCE`.
+    objective: ObjectiveMode,
+    /// `--seed N`: reshuffles the pairings among equally good ones, reproducibly.
+    seed: Option<u64>,
+    /// `--alternatives K`: how many distinct pairings to find, best first.
+    alternatives: Option<usize>,
+    /// `--min-difference M`: how many pairs each alternative must change from every earlier one.
+    min_difference: Option<usize>,
+    /// `--repair FILE`: a pairings file to change as little as possible, e.g. after someone
+    /// dropped out.
+    repair: Option<String>,
+    /// `--change-penalty P`: what keeping each old pair is worth when repairing.
+    change_penalty: Option<f64>,
+    /// `--append FILE`: a pairings file already handed out, which new participants are added to.
+    append: Option<String>,
+    /// `--max-changes K`: how many existing pairs may change when appending.
+    max_changes: Option<usize>,
+    /// `--preferences FILE`: preference scores by pair, overriding shared interests.
+    preferences: Option<String>,
+    /// `--preference-weight W`: what a preference score of 1 adds to a pair's weight.
+    preference_weight: Option<f64>,
+    /// `--min-cycle-length K`: the shortest exchange cycle allowed.
+    min_cycle_length: Option<usize>,
+    /// `--single-ring`: one card each, in a single ring if possible.
+    single_ring: bool,
+    /// `--strongly-connected`: no isolated groups in the exchange.
+    strongly_connected: bool,
+    /// `--coverage-priority first|WEIGHT`: how much getting a card to every receive-only
+    /// participant matters.
+    coverage_priority: Option<CoveragePriority>,
+    /// `--shipping-costs FILE`: costs between countries, with `--domestic-cost`,
+    /// `--international-cost` and `--cost-per-km` for everything else.
+    shipping_costs: ShippingCosts,
+    /// `--minimize-shipping`: make shipping as cheap as possible once everything else is optimal.
+    minimize_shipping: bool,
+    /// `--time-limit SECONDS`, `--gap-limit GAP` and `--node-limit N`: when to settle for the
+    /// best pairing found so far.
+    limits: SolveLimits,
+    /// `--rounding`: also round the LP relaxation into pairings while searching.
+    rounding: bool,
+    /// `--output FILE`: where to write the pairings.
+    output: Option<String>,
+}
 
-pub fn main() {
-    // read a vector of integers from argv
+pub fn main() -> Result<()> {
     let args: Vec<String> = std::env::args().collect();
-    let mut nums: Vec<i32> = parse_shorthand_args(&args[1..]);
-    println!("Numbers: {:?}", nums);
+    let options = parse_options(&args[1..])?;
+    let problem = build_problem(&options)?;
 
-    // sort nums in ascending order
-    nums.sort();
+    // generate pairings; Ctrl-C stops early and keeps the best pairing found
+    interrupt_on_ctrl_c()?;
+    println!("Attempting to solve... (press Ctrl-C to stop with the best pairing so far)");
+    let solutions = match options.alternatives {
+        Some(count) => problem.solve_distinct(count, options.min_difference.unwrap_or(1))?,
+        None => vec![problem.solve()?],
+    };
+    let solution = &solutions[0];
+    let stats = &solution.stats;
+    if stats.is_optimal() {
+        println!("Solved. Objective value: {}", stats.objective);
+    } else if stats.status == Status::UserInterrupt {
+        println!(
+            "Interrupted; best pairing found is not proven optimal. Objective value: {}, bound: \
+             {}, gap: {:.2}%",
+            stats.objective,
+            stats.dual_bound,
+            stats.gap * 100.
+        );
+    } else {
+        println!(
+            "Stopped early ({:?}); best pairing found is not proven optimal. Objective value: {}, \
+             bound: {}, gap: {:.2}%",
+            stats.status,
+            stats.objective,
+            stats.dual_bound,
+            stats.gap * 100.
+        );
+    }
+    print_greedy_start(stats);
 
-    // generate pairings
-    let pairings = generate_pairings(&nums).unwrap();
+    print_solution(&problem, solution);
+    if options.alternatives.is_some() {
+        print_alternatives(&solutions);
+    }
 
-    let mut pairings_by_sender: Vec<Vec<usize>> = Vec::new();
-    let mut pairings_by_receiver: Vec<Vec<usize>> = Vec::new();
-    for _ in 0..nums.len() {
-        pairings_by_sender.push(Vec::new());
-        pairings_by_receiver.push(Vec::new());
+    let output = options.output.as_deref().unwrap_or("solution.csv");
+    for (k, solution) in solutions.iter().enumerate() {
+        let output = numbered_path(output, k);
+        save_pairings(solution, &output)?;
+        println!("Pairings saved as: {}", output);
+
+        let filename = numbered_path("solution.png", k);
+
+        // Create visualization
+        match visualize_solution_matrix(solution, &filename) {
+            Ok(()) => println!("Matrix visualization saved as: {}", filename),
+            Err(e) => eprintln!("Failed to create visualization: {}", e),
+        }
     }
 
-    for (i, j) in &pairings {
-        pairings_by_sender[*i].push(*j);
-        pairings_by_receiver[*j].push(*i);
+    Ok(())
+}
+
+fn build_problem(options: &Options) -> Result<PairingProblem> {
+    let mut problem = match &options.roster {
+        Some(path) => PairingProblem::from_participants(load_roster(path)?),
+        None => {
+            if options
+                .card_counts
+                .iter()
+                .all(|(send, receive)| send == receive)
+            {
+                let counts: Vec<u32> = options.card_counts.iter().map(|&(send, _)| send).collect();
+                println!("Numbers: {:?}", counts);
+                PairingProblem::from_card_counts(&counts)
+            } else {
+                println!("Numbers (send:receive): {:?}", options.card_counts);
+                PairingProblem::from_participants(
+                    options
+                        .card_counts
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &(send, receive))| {
+                            let participant =
+                                Participant::new(format!("P{}", i + 1), send.max(receive));
+                            if send == receive {
+                                participant
+                            } else {
+                                participant
+                                    .sends(CardRange::up_to(send))
+                                    .receives(CardRange::up_to(receive))
+                            }
+                        })
+                        .collect(),
+                )
+            }
+        }
+    };
+    for names in &options.exclusion_groups {
+        let members = names
+            .iter()
+            .map(|name| participant_index(&problem, name))
+            .collect::<Result<_>>()?;
+        problem = problem.exclusion_group(members);
+    }
+    for (sender, receiver) in &options.forbidden_pairs {
+        let sender = participant_index(&problem, sender)?;
+        let receiver = participant_index(&problem, receiver)?;
+        problem = problem.forbid_pair(sender, receiver);
+    }
+    for (sender, receiver) in &options.required_pairs {
+        let sender = participant_index(&problem, sender)?;
+        let receiver = participant_index(&problem, receiver)?;
+        problem = problem.require_pair(sender, receiver);
+    }
+    if let Some(min_cards) = options.min_cards {
+        problem = problem.min_cards(min_cards);
+    }
+    problem = problem.optimize(options.objective.clone());
+    if let Some(path) = &options.repair {
+        problem = problem.previous_solution(&load_pairings(path)?);
+    }
+    if let Some(penalty) = options.change_penalty {
+        problem = problem.change_penalty(penalty);
+    }
+    if let Some(path) = &options.append {
+        let pairings = load_pairings(path)?
+            .iter()
+            .map(|(sender, receiver)| {
+                Ok((
+                    participant_index(&problem, sender)?,
+                    participant_index(&problem, receiver)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        problem = problem
+            .append_to(&pairings)
+            .allow_changes(options.max_changes.unwrap_or(0));
+    }
+    if let Some(path) = &options.preferences {
+        problem = problem.preferences(&load_preferences(path)?);
+    }
+    if let Some(weight) = options.preference_weight {
+        problem = problem.preference_weight(weight);
     }
+    if let Some(length) = options.min_cycle_length {
+        problem = problem.forbid_short_cycles(length);
+    }
+    if options.single_ring {
+        problem = problem.single_ring();
+    }
+    if options.strongly_connected {
+        problem = problem.require_strong_connectivity();
+    }
+    if let Some(priority) = options.coverage_priority {
+        problem = problem.coverage_priority(priority);
+    }
+    problem = problem.shipping_costs(options.shipping_costs.clone());
+    if options.minimize_shipping {
+        problem = problem.minimize_shipping();
+    }
+    if let Some(time) = options.limits.time {
+        problem = problem.time_limit(time);
+    }
+    if let Some(gap) = options.limits.gap {
+        problem = problem.gap_limit(gap);
+    }
+    if let Some(nodes) = options.limits.nodes {
+        problem = problem.node_limit(nodes);
+    }
+    if let Some(seed) = options.seed {
+        problem = problem.seed(seed);
+    }
+    if options.rounding {
+        problem = problem.rounding_heuristic();
+    }
+    problem = problem.on_progress(print_progress);
+    if !options.history.is_empty() {
+        problem = problem
+            .history(&load_history(&options.history)?)
+            .handle_repeats(options.history_mode);
+    }
+    Ok(problem)
+}
 
-    for i in 0..nums.len() {
-        println!("Participant {} requests {} cards (actual sent: {}, received: {})", i, nums[i], pairings_by_sender[i].len(), pairings_by_receiver[i].len());
+fn print_solution(problem: &PairingProblem, solution: &PairingSolution) {
+    let participants = &solution.participants;
+    for (i, participant) in participants.iter().enumerate() {
+        let sent = solution.sent_by(i);
+        let received = solution.received_by(i);
+        let min_cards = problem.min_cards_for(i);
+        let minimum = if min_cards > 0 {
+            format!(", minimum: {}", min_cards)
+        } else {
+            String::new()
+        };
+        let request = if participant.role == Role::SendOnly {
+            format!("sends {} cards only", participant.send_range())
+        } else if participant.role == Role::ReceiveOnly {
+            format!("receives {} cards only", participant.receive_range())
+        } else if participant.sends.is_none() && participant.receives.is_none() {
+            format!("requests {} cards", participant.num_cards)
+        } else {
+            format!(
+                "sends {} and receives {} cards{}",
+                participant.send_range(),
+                participant.receive_range(),
+                if participant.balanced {
+                    ", balanced"
+                } else {
+                    ""
+                }
+            )
+        };
+        println!(
+            "{} {} (actual sent: {}, received: {}{})",
+            participant.name,
+            request,
+            sent.len(),
+            received.len(),
+            minimum
+        );
+
+        if participant.country.is_some() || participant.international_mix != InternationalMix::Any {
+            let abroad = received
+                .iter()
+                .filter(|&&j| problem.is_international(j, i))
+                .count();
+            let wanted = match participant.international_mix {
+                InternationalMix::Any => String::new(),
+                InternationalMix::AtLeast(k) => format!(", wants at least {}", k),
+                InternationalMix::DomesticOnly => ", wants domestic only".to_string(),
+            };
+            println!(
+                "mix: {} domestic, {} from abroad{}",
+                received.len() - abroad,
+                abroad,
+                wanted
+            );
+        }
 
-        for j in &pairings_by_sender[i] {
-            println!("send: {}", j);
+        for j in &sent {
+            println!("send: {}", describe(&participants[*j]));
         }
 
-        for j in &pairings_by_receiver[i] {
-            println!("receive: {}", j);
+        for j in &received {
+            println!("receive: {}", participants[*j].name);
         }
     }
 
-    println!("Total number of participants: {}", nums.len());
-    println!("Total number of pairings: {}", pairings.len());
+    let excluded_pairs = problem.group_excluded_pairs().len();
+    let violations = problem.violated_exclusions(solution);
+    if violations.is_empty() {
+        println!(
+            "Exclusions respected: {} pairs ruled out by {} groups and shared households, {} pairs \
+             forbidden explicitly",
+            excluded_pairs,
+            problem.exclusion_groups().len(),
+            problem.forbidden_pairs().len()
+        );
+    } else {
+        for (i, j) in &violations {
+            println!(
+                "Exclusion violated: {} sends to {}",
+                participants[*i].name, participants[*j].name
+            );
+        }
+    }
 
-    let filename = "solution.png";
-    
-    // Create visualization
-    if let Err(e) = visualize_solution_matrix(&pairings, &nums, &filename) {
-        eprintln!("Failed to create visualization: {}", e);
+    if !problem.required_pairs().is_empty() {
+        let missing = problem.missing_required_pairs(solution);
+        if missing.is_empty() {
+            println!(
+                "Required pairs included: {}",
+                problem.required_pairs().len()
+            );
+        }
+        for (i, j) in &missing {
+            println!(
+                "Required pair missing: {} does not send to {}",
+                participants[*i].name, participants[*j].name
+            );
+        }
     }
-}
 
-/// Parse command line arguments that support shorthand notation.
-/// Examples:
-/// - "3" -> [3]
-/// - "3x4" -> [3, 3, 3, 3]
-/// - "1x3 2x3 3x4" -> [1, 1, 1, 2, 2, 2, 3, 3, 3, 3]
-fn parse_shorthand_args(args: &[String]) -> Vec<i32> {
-    let mut result = Vec::new();
-    
-    for arg in args {
-        if let Some(x_pos) = arg.find('x') {
-            // Parse "NxM" format
-            let (num_str, count_str) = arg.split_at(x_pos);
-            let count_str = &count_str[1..]; // Skip the 'x'
-            
-            let num: i32 = num_str.parse().expect(&format!("Invalid number: {}", num_str));
-            let count: usize = count_str.parse().expect(&format!("Invalid count: {}", count_str));
-            
-            for _ in 0..count {
-                result.push(num);
-            }
+    if !problem.past_pairs().is_empty() {
+        print_repeats(problem, solution);
+    }
+
+    print_fulfillment(solution);
+
+    let receive_only = problem.receive_only();
+    if !receive_only.is_empty() {
+        let uncovered: Vec<&str> = receive_only
+            .iter()
+            .filter(|&&i| solution.received_by(i).is_empty())
+            .map(|&i| participants[i].name.as_str())
+            .collect();
+        println!(
+            "Receive-only participants covered: {} of {}",
+            receive_only.len() - uncovered.len(),
+            receive_only.len()
+        );
+        for name in &uncovered {
+            println!("uncovered: {}", name);
+        }
+    }
+
+    if problem.has_preferences() {
+        print_preference_satisfaction(problem, solution);
+    }
+
+    if !problem.existing_pairings().is_empty() {
+        print_appended(problem, solution);
+    }
+
+    if !problem.previous_pairings().is_empty() {
+        print_partner_changes(problem, solution);
+    }
+
+    let levels: Vec<_> = solution
+        .stats
+        .levels
+        .iter()
+        .filter(|level| level.objective != Objective::Shuffle)
+        .collect();
+    if levels.len() > 1 {
+        for (k, level) in levels.iter().enumerate() {
+            println!(
+                "Objective level {} ({}): {}",
+                k + 1,
+                level.objective,
+                level.value
+            );
+        }
+    }
+
+    if let Some(seed) = problem.random_seed() {
+        println!(
+            "Seed: {} (rerun with another --seed for a different pairing)",
+            seed
+        );
+    }
+    if problem.is_single_ring() {
+        let rings: Vec<Vec<usize>> = solution
+            .components()
+            .into_iter()
+            .filter(|members| members.len() > 1)
+            .collect();
+        if rings.len() == 1 {
+            println!("Single ring through all {} participants", rings[0].len());
         } else {
-            // Parse single number
-            let num: i32 = arg.parse().expect(&format!("Invalid number: {}", arg));
-            result.push(num);
+            println!(
+                "A single ring is not possible; using the fewest rings instead: {}",
+                rings.len()
+            );
         }
+        for ring in &rings {
+            let names: Vec<&str> = ring_order(solution, ring)
+                .into_iter()
+                .map(|i| participants[i].name.as_str())
+                .collect();
+            println!("ring: {}", names.join(" -> "));
+        }
+    }
+
+    print_components(solution);
+
+    if problem.minimizes_shipping() || participants.iter().any(|p| p.country.is_some()) {
+        let international = solution
+            .pairings
+            .iter()
+            .filter(|&&(i, j)| problem.is_international(i, j))
+            .count();
+        println!(
+            "Shipping cost: {:.2} ({} of {} cards international)",
+            problem.total_shipping_cost(solution),
+            international,
+            solution.pairings.len()
+        );
+    }
+
+    if let Some(length) = solution.shortest_cycle() {
+        println!(
+            "Shortest exchange cycle: {} (minimum allowed: {})",
+            length,
+            problem.min_cycle_length()
+        );
     }
-    
-    result
+    println!("Total number of participants: {}", participants.len());
+    println!("Total number of pairings: {}", solution.pairings.len());
+    println!(
+        "Solve time: {:.2}s ({} nodes, {} variables, {} constraints)",
+        solution.stats.solving_time,
+        solution.stats.n_nodes,
+        solution.stats.n_vars,
+        solution.stats.n_conss
+    );
 }
 
-pub fn generate_pairings(cards_for_participant: &Vec<i32>) -> Result<Vec<(usize, usize)>> {
-    let n = cards_for_participant.len();
-
-    let mut model = Model::new()
-        .hide_output()
-        .include_default_plugins()
-        .create_prob("pairings")
-        .set_obj_sense(ObjSense::Maximize);
-
-    // x[i][j] is 1 if person i sends a card to person j
-    let mut x = Vec::new();
-    for _ in 0..n {
-        let mut row = Vec::new();
-        for _ in 0..n {
-            row.push(model.add_var(0., 1., 1., "adjacency", VarType::Binary));
+/// Summarizes how much of their request everyone received: the worst and mean ratio, and how
+/// many people fall in each tenth.
+/// Reports the past pairings `solution` uses again or, if repeats are forbidden, how many cards
+/// the ban costs.
+fn print_repeats(problem: &PairingProblem, solution: &PairingSolution) {
+    if problem.history_mode() == HistoryMode::Forbid {
+        match problem.repeat_ban_cost(solution) {
+            Ok(Some(cost)) if cost.cards_lost > 0 => println!(
+                "Cards lost to the repeat ban: {} (allowing {} repeats would send them)",
+                cost.cards_lost, cost.repeats_needed
+            ),
+            Ok(_) => println!("Cards lost to the repeat ban: 0"),
+            Err(e) => eprintln!("Failed to work out what the repeat ban costs: {}", e),
         }
-        x.push(row);
+        return;
+    }
+
+    let participants = &solution.participants;
+    let repeats = problem.repeated_pairs(solution);
+    let forced = repeats
+        .iter()
+        .filter(|&&(i, j)| {
+            problem.required_pairs().contains(&(i, j)) || problem.fixed_pair(i, j) == Some(true)
+        })
+        .count();
+    println!(
+        "Repeated pairings kept: {} ({} of them required or fixed)",
+        repeats.len(),
+        forced
+    );
+    for (i, j) in &repeats {
+        println!(
+            "repeat: {} sends to {}",
+            participants[*i].name, participants[*j].name
+        );
     }
+}
 
-    // Nobody sends a card to themself.
-    for i in 0..n {
-        model.add_cons(vec![&x[i][i]], &[1.], 0., 0., "no_self_exchange");
+fn print_fulfillment(solution: &PairingSolution) {
+    let ratios: Vec<f64> = solution
+        .fulfillment_ratios()
+        .into_iter()
+        .flatten()
+        .collect();
+    if ratios.is_empty() {
+        return;
     }
+    let min = ratios.iter().copied().fold(f64::INFINITY, f64::min);
+    let mean = ratios.iter().sum::<f64>() / ratios.len() as f64;
+    println!(
+        "Fulfillment ratio: min {:.0}%, mean {:.0}%",
+        min * 100.,
+        mean * 100.
+    );
 
-    // Nobody sends a card to someone who sent a card to them.
-    for i in 0..n {
-        for j in (i + 1)..n {
-            model.add_cons(
-                vec![&x[i][j], &x[j][i]],
-                &[1., 1.],
-                0.,
-                1.,
-                "no_mutual_exchange",
+    // Bucket k holds ratios in [k/10, (k+1)/10), with a full request counted in the last bucket.
+    let mut buckets = [0; 10];
+    for ratio in &ratios {
+        buckets[((ratio * 10.) as usize).min(9)] += 1;
+    }
+    for (k, count) in buckets.iter().enumerate() {
+        if *count > 0 {
+            println!(
+                "  {:>3}%-{:>3}%: {}",
+                k * 10,
+                (k + 1) * 10,
+                "#".repeat(*count)
             );
         }
     }
+}
+
+/// Shows how well everyone's cards match their preferences: the mean score of the cards each
+/// person receives.
+fn print_preference_satisfaction(problem: &PairingProblem, solution: &PairingSolution) {
+    let satisfaction = problem.preference_satisfaction(solution);
+    let scores: Vec<f64> = satisfaction.iter().flatten().copied().collect();
+    if scores.is_empty() {
+        return;
+    }
+    println!(
+        "Preference satisfaction: mean {:.2}, lowest {:.2}",
+        scores.iter().sum::<f64>() / scores.len() as f64,
+        scores.iter().copied().fold(f64::INFINITY, f64::min)
+    );
+    for (participant, score) in solution.participants.iter().zip(&satisfaction) {
+        if let Some(score) = score {
+            println!("satisfaction: {} {:.2}", participant.name, score);
+        }
+    }
+}
 
-    // Nobody sends more cards than they signed up for.
-    for i in 0..n {
-        let num_cards = cards_for_participant[i];
-        model.add_cons(
-            x[i].iter().collect(),
-            &vec![1.0; n],
-            0.,
-            num_cards as f64,
-            "num_cards",
+/// Lists who joined the existing pairing, and which existing pairs changed to fit them in.
+fn print_appended(problem: &PairingProblem, solution: &PairingSolution) {
+    let participants = &solution.participants;
+    let new: Vec<&str> = (0..participants.len())
+        .filter(|&i| problem.is_new(i))
+        .map(|i| participants[i].name.as_str())
+        .collect();
+    println!("New participants: {} ({})", new.len(), new.join(", "));
+
+    let changed = problem.changed_existing_pairs(solution);
+    println!("Existing pairs changed: {}", changed.len());
+    for (i, j) in &changed {
+        let change = if problem.existing_pairings().contains(&(*i, *j)) {
+            "dropped"
+        } else {
+            "added"
+        };
+        println!(
+            "{}: {} sends to {}",
+            change, participants[*i].name, participants[*j].name
         );
     }
+}
 
-    // Everyone receives a card for every card they send.
-    for i in 0..n {
-        // Collect variables representing cards that i sends, and give them a coefficient of +1.
-        let mut vars: Vec<_> = x[i].iter().cloned().collect();
-        let mut coefs = vec![1.0; n];
-        // Collect variables representing cards that i receives, and give them a coefficient of -1.
-        vars.extend(x.iter().map(|row| row[i].clone()));
-        coefs.extend_from_slice(vec![-1.0; n].as_ref());
-        model.add_cons(vars.iter().collect(), &coefs, 0., 0., "card_balance");
+/// Lists everyone who got a new partner compared to the pairing being repaired.
+fn print_partner_changes(problem: &PairingProblem, solution: &PairingSolution) {
+    let changes = problem.partner_changes(solution);
+    println!("Participants with changed partners: {}", changes.len());
+    for change in &changes {
+        let mut parts = Vec::new();
+        for (label, names) in [
+            ("now sends to", &change.new_recipients),
+            ("no longer sends to", &change.lost_recipients),
+            ("now receives from", &change.new_senders),
+            ("no longer receives from", &change.lost_senders),
+        ] {
+            if !names.is_empty() {
+                parts.push(format!("{} {}", label, names.join(", ")));
+            }
+        }
+        println!("changed: {} {}", change.participant, parts.join("; "));
     }
+}
 
-    println!("Attempting to solve...");
-    let solved_model = model.solve();
-    if solved_model.status() != Status::Optimal {
-        anyhow::bail!("Optimal solution not found");
+/// Reports the strongly connected components: groups who can all reach each other through the
+/// chain of sent cards. People who do not both send and receive cards are left out.
+fn print_components(solution: &PairingSolution) {
+    let components: Vec<Vec<usize>> = solution
+        .components()
+        .into_iter()
+        .filter(|members| {
+            members
+                .iter()
+                .any(|&i| !solution.sent_by(i).is_empty() && !solution.received_by(i).is_empty())
+        })
+        .collect();
+    if components.len() <= 1 {
+        println!("Exchange graph is strongly connected");
+        return;
     }
+    println!("Strongly connected components: {}", components.len());
+    for members in &components {
+        let names: Vec<&str> = members
+            .iter()
+            .map(|&i| solution.participants[i].name.as_str())
+            .collect();
+        println!("component: {}", names.join(", "));
+    }
+}
 
-    let obj_val = solved_model.obj_val();
-    println!("Solved. Objective value: {}", obj_val);
+/// The members of a ring in the order cards are passed around it.
+fn ring_order(solution: &PairingSolution, ring: &[usize]) -> Vec<usize> {
+    let mut order = vec![ring[0]];
+    while let Some(&next) = solution.sent_by(*order.last().unwrap()).first() {
+        if next == ring[0] {
+            break;
+        }
+        order.push(next);
+    }
+    order
+}
+
+/// Shows how close the greedy pairing the solver started from came to the final one.
+fn print_greedy_start(stats: &SolveStats) {
+    let Some(greedy) = stats.greedy_objective else {
+        println!("Greedy start: broke a rule, so the solver started from scratch");
+        return;
+    };
+    let behind = stats.objective - greedy;
+    let relative = if stats.objective != 0. {
+        format!(" ({:.1}%)", behind / stats.objective.abs() * 100.)
+    } else {
+        String::new()
+    };
+    println!(
+        "Greedy start: objective value {}, {} short of the final pairing{}",
+        greedy, behind, relative
+    );
+}
 
-    let sol = solved_model.best_sol().unwrap();
+/// Streams each better pairing the solver finds to stderr, so long solves show signs of life.
+fn print_progress(progress: &Progress) {
+    eprintln!(
+        "[{:>7.1}s] level {} ({}): {} pairs, objective {:.2}, bound {:.2}, gap {:.2}%",
+        progress.elapsed,
+        progress.level + 1,
+        progress.objective,
+        progress.pairings.len(),
+        progress.value,
+        progress.dual_bound,
+        progress.gap * 100.
+    );
+}
 
-    let mut result: Vec<(usize, usize)> = Vec::new();
-    for i in 0..n {
-        for j in 0..n {
-            if sol.val(&x[i][j]) >= 0.9 {
-                result.push((i, j));
-            }
+/// Summarizes how the alternatives differ: what each one changes from the first, and how far
+/// apart every two of them are.
+fn print_alternatives(solutions: &[PairingSolution]) {
+    println!("Found {} distinct pairings", solutions.len());
+    let first = &solutions[0];
+    let participants = &first.participants;
+    for (k, solution) in solutions.iter().enumerate().skip(1) {
+        let added = solution.pairings_not_in(first);
+        let removed = first.pairings_not_in(solution);
+        let proven = if solution.stats.is_optimal() {
+            ""
+        } else {
+            " (not proven optimal)"
+        };
+        println!(
+            "Alternative {}: objective value {}{}, {} pairs added and {} removed from alternative 1",
+            k + 1,
+            solution.stats.objective,
+            proven,
+            added.len(),
+            removed.len()
+        );
+        for (i, j) in &added {
+            println!(
+                "  + {} sends to {}",
+                participants[*i].name, participants[*j].name
+            );
+        }
+        for (i, j) in &removed {
+            println!(
+                "  - {} sends to {}",
+                participants[*i].name, participants[*j].name
+            );
         }
     }
 
-    Ok(result)
+    if solutions.len() > 2 {
+        println!("Pairs changed between alternatives:");
+        for (k, solution) in solutions.iter().enumerate() {
+            let distances: Vec<String> = solutions
+                .iter()
+                .map(|other| format!("{:>4}", solution.distance(other)))
+                .collect();
+            println!("  {:>3}: {}", k + 1, distances.join(""));
+        }
+    }
 }
 
-/// Visualize the solution matrix as a PNG image where each cell is a 9x9 square with 1px white borders
-pub fn visualize_solution_matrix(
-    pairings: &Vec<(usize, usize)>,
-    nums: &Vec<i32>,
-    filename: &str,
-) -> Result<()> {
-    let n = nums.len();
-    let cell_size = 9;
-    let border_size = 1;
-    // Image size: n cells of size cell_size + (n+1) borders of size border_size
-    let image_size = n * cell_size + (n + 1) * border_size;
-    
-    // Create a new RGB image with white background (for borders)
-    let mut img: RgbImage = ImageBuffer::new(image_size as u32, image_size as u32);
-    let white = Rgb([255, 255, 255]);
-    
-    // Fill the entire image with white (this creates the border effect)
-    for y in 0..image_size {
-        for x in 0..image_size {
-            img.put_pixel(x as u32, y as u32, white);
+/// `path` for the first solution, and `path` with `-2`, `-3`, ... before its extension for the
+/// alternatives after it.
+fn numbered_path(path: &str, index: usize) -> String {
+    if index == 0 {
+        return path.to_string();
+    }
+    match path.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.contains('/') => {
+            format!("{}-{}.{}", stem, index + 1, extension)
         }
+        _ => format!("{}-{}", path, index + 1),
+    }
+}
+
+/// A participant's name, followed by their address if the roster has one.
+fn describe(participant: &Participant) -> String {
+    if participant.address.is_empty() {
+        participant.name.clone()
+    } else {
+        format!("{} <{}>", participant.name, participant.address)
     }
-    
-    // Count how many pairings each row (sender) and column (receiver) has
-    let mut row_counts = vec![0; n];
-    let mut col_counts = vec![0; n];
-    for (i, j) in pairings {
-        row_counts[*i] += 1;
-        col_counts[*j] += 1;
-    }
-    
-    // Find the maximum counts for normalization
-    let max_row_count = *row_counts.iter().max().unwrap_or(&1);
-    let max_col_count = *col_counts.iter().max().unwrap_or(&1);
-    
-    // Define colors
-    let blue = Rgb([0, 100, 200]);   // Has pairing
-    
-    // Fill each cell
-    for row in 0..n {
-        for col in 0..n {
-            // Calculate cell position accounting for borders
-            let start_x = border_size + col * (cell_size + border_size);
-            let start_y = border_size + row * (cell_size + border_size);
-            
-            // Check if this cell has a pairing
-            let has_pairing = pairings.iter().any(|(i, j)| *i == row && *j == col);
-            
-            if has_pairing {
-                // Fill entire cell with blue
-                for dy in 0..cell_size {
-                    for dx in 0..cell_size {
-                        let x = start_x + dx;
-                        let y = start_y + dy;
-                        if x < image_size && y < image_size {
-                            img.put_pixel(x as u32, y as u32, blue);
-                        }
-                    }
+}
+
+/// Looks up a participant named on the command line.
+fn participant_index(problem: &PairingProblem, name: &str) -> Result<usize> {
+    problem
+        .index_of(name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown participant: {}", name))
+}
+
+/// Shown when the command line has an option that does not exist.
+const USAGE: &str = "\
+Usage: pairings [OPTIONS] (--roster FILE | CARDS...)
+
+CARDS are card counts, one per participant: N sends and receives N cards, S:R sends S and
+receives R, and a trailing xK repeats either for K participants, as in 3x4 or 2:1x3.
+
+Options:
+  --roster FILE                 --exclude NAME,NAME,...       --forbid SENDER:RECEIVER
+  --require SENDER:RECEIVER     --history FILE                --history-mode forbid|penalize
+  --repeat-penalty P            --repeat-decay D              --min-cards N|P%
+  --objective total|fairness|LEVEL[:TOLERANCE],...            --seed N
+  --alternatives K              --min-difference M            --repair FILE
+  --change-penalty P            --append FILE                 --max-changes K
+  --preferences FILE            --preference-weight W         --min-cycle-length K
+  --single-ring                 --strongly-connected          --coverage-priority first|WEIGHT
+  --shipping-costs FILE         --domestic-cost C             --international-cost C
+  --cost-per-km C               --minimize-shipping           --time-limit SECONDS
+  --gap-limit GAP               --node-limit N                --rounding
+  --output FILE";
+
+fn parse_options(args: &[String]) -> Result<Options> {
+    let mut options = Options::default();
+    let mut shorthand = Vec::new();
+    let mut repeat_penalty = None;
+    let mut repeat_decay = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--roster" => options.roster = Some(option_value(&mut args, arg)?.clone()),
+            "--exclude" => options.exclusion_groups.push(
+                option_value(&mut args, arg)?
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .collect(),
+            ),
+            "--forbid" => options
+                .forbidden_pairs
+                .push(parse_pair(option_value(&mut args, arg)?)?),
+            "--require" => options
+                .required_pairs
+                .push(parse_pair(option_value(&mut args, arg)?)?),
+            "--history" => options.history.push(option_value(&mut args, arg)?.clone()),
+            "--history-mode" => {
+                options.history_mode = match option_value(&mut args, arg)?.as_str() {
+                    "forbid" => HistoryMode::Forbid,
+                    "penalize" => HistoryMode::Penalize {
+                        penalty: DEFAULT_REPEAT_PENALTY,
+                        decay: DEFAULT_REPEAT_DECAY,
+                    },
+                    other => anyhow::bail!("Unknown history mode: {}", other),
                 }
-            } else {
-                let color_scale = 128.0;
-                // Split cell diagonally into two triangles
-                // Calculate grey tones for row (bottom-left triangle) and column (top-right triangle)
-                let row_activity_ratio = row_counts[row] as f32 / max_row_count as f32;
-                let row_grey_value = (256.0 - (row_activity_ratio * color_scale)) as u8; // 240 down to 200
-                let row_color = Rgb([row_grey_value, row_grey_value, row_grey_value]);
-                
-                let col_activity_ratio = col_counts[col] as f32 / max_col_count as f32;
-                let col_grey_value = (256.0 - (col_activity_ratio * color_scale)) as u8; // 240 down to 200
-                let col_color = Rgb([col_grey_value, col_grey_value, col_grey_value]);
-                
-                // Fill the 9x9 square with diagonal split
-                for dy in 0..cell_size {
-                    for dx in 0..cell_size {
-                        let x = start_x + dx;
-                        let y = start_y + dy;
-                        if x < image_size && y < image_size {
-                            // Determine which triangle this pixel is in
-                            // Top-left to bottom-right diagonal: if dx >= dy, it's top-right triangle (column-based)
-                            // if dx < dy, it's bottom-left triangle (row-based)
-                            let color = if dx >= dy {
-                                col_color  // Top-right triangle: column activity
-                            } else {
-                                row_color  // Bottom-left triangle: row activity
-                            };
-                            img.put_pixel(x as u32, y as u32, color);
-                        }
-                    }
+            }
+            "--repeat-penalty" => repeat_penalty = Some(parse_value(&mut args, arg)?),
+            "--repeat-decay" => repeat_decay = Some(parse_value(&mut args, arg)?),
+            "--min-cards" => options.min_cards = Some(option_value(&mut args, arg)?.parse()?),
+            "--objective" => {
+                options.objective = match option_value(&mut args, arg)?.as_str() {
+                    "total" => ObjectiveMode::MaxTotal,
+                    "fairness" => ObjectiveMode::MaxMinFairness,
+                    levels => ObjectiveMode::Lexicographic(
+                        levels
+                            .split(',')
+                            .map(|level| level.parse())
+                            .collect::<Result<_>>()?,
+                    ),
                 }
             }
+            "--seed" => options.seed = Some(parse_value(&mut args, arg)?),
+            "--alternatives" => options.alternatives = Some(parse_value(&mut args, arg)?),
+            "--min-difference" => options.min_difference = Some(parse_value(&mut args, arg)?),
+            "--repair" => options.repair = Some(option_value(&mut args, arg)?.clone()),
+            "--change-penalty" => options.change_penalty = Some(parse_value(&mut args, arg)?),
+            "--append" => options.append = Some(option_value(&mut args, arg)?.clone()),
+            "--max-changes" => options.max_changes = Some(parse_value(&mut args, arg)?),
+            "--preferences" => options.preferences = Some(option_value(&mut args, arg)?.clone()),
+            "--preference-weight" => options.preference_weight = Some(parse_value(&mut args, arg)?),
+            "--min-cycle-length" => options.min_cycle_length = Some(parse_value(&mut args, arg)?),
+            "--single-ring" => options.single_ring = true,
+            "--strongly-connected" => options.strongly_connected = true,
+            "--coverage-priority" => {
+                let value = option_value(&mut args, arg)?;
+                options.coverage_priority =
+                    Some(match value.as_str() {
+                        "first" => CoveragePriority::First,
+                        weight => CoveragePriority::Weighted(weight.parse().map_err(|_| {
+                            anyhow::anyhow!("Invalid value for {}: {}", arg, value)
+                        })?),
+                    });
+            }
+            "--shipping-costs" => {
+                let costs = load_shipping_costs(option_value(&mut args, arg)?)?;
+                options.shipping_costs.country_costs = costs.country_costs;
+            }
+            "--domestic-cost" => options.shipping_costs.domestic = parse_value(&mut args, arg)?,
+            "--international-cost" => {
+                options.shipping_costs.international = parse_value(&mut args, arg)?
+            }
+            "--cost-per-km" => options.shipping_costs.per_km = parse_value(&mut args, arg)?,
+            "--minimize-shipping" => options.minimize_shipping = true,
+            "--time-limit" => options.limits.time = Some(parse_value(&mut args, arg)?),
+            "--gap-limit" => options.limits.gap = Some(parse_value(&mut args, arg)?),
+            "--node-limit" => options.limits.nodes = Some(parse_value(&mut args, arg)?),
+            "--rounding" => options.rounding = true,
+            "--output" => options.output = Some(option_value(&mut args, arg)?.clone()),
+            flag if flag.starts_with("--") => {
+                anyhow::bail!("Unknown option: {}\n\n{}", flag, USAGE)
+            }
+            _ => shorthand.push(arg.clone()),
         }
     }
-    
-    // Save the image
-    img.save(filename)?;
-    println!("Matrix visualization saved as: {}", filename);
-    
-    Ok(())
+
+    if let HistoryMode::Penalize { penalty, decay } = &mut options.history_mode {
+        *penalty = repeat_penalty.unwrap_or(*penalty);
+        *decay = repeat_decay.unwrap_or(*decay);
+    } else if repeat_penalty.is_some() || repeat_decay.is_some() {
+        anyhow::bail!("--repeat-penalty and --repeat-decay need --history-mode penalize");
+    }
+
+    if options
+        .limits
+        .time
+        .is_some_and(|time| time.is_nan() || time < 0.)
+    {
+        anyhow::bail!("--time-limit must not be negative");
+    }
+    if options
+        .limits
+        .gap
+        .is_some_and(|gap| gap.is_nan() || gap < 0.)
+    {
+        anyhow::bail!("--gap-limit must not be negative");
+    }
+    if options.change_penalty.is_some() && options.repair.is_none() {
+        anyhow::bail!("--change-penalty needs --repair");
+    }
+    if options.max_changes.is_some() && options.append.is_none() {
+        anyhow::bail!("--max-changes needs --append");
+    }
+    if options.alternatives == Some(0) {
+        anyhow::bail!("--alternatives must be at least 1");
+    }
+    if options.min_difference.is_some() && options.alternatives.is_none() {
+        anyhow::bail!("--min-difference needs --alternatives");
+    }
+
+    if options.roster.is_some() && !shorthand.is_empty() {
+        anyhow::bail!("Pass either --roster or card counts, not both");
+    }
+    options.card_counts = parse_shorthand_args(&shorthand)?;
+
+    Ok(options)
+}
+
+/// Parses a `SENDER:RECEIVER` pair of participant names.
+fn parse_pair(arg: &str) -> Result<(String, String)> {
+    let (sender, receiver) = arg
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Expected SENDER:RECEIVER, got {}", arg))?;
+    Ok((sender.trim().to_string(), receiver.trim().to_string()))
+}
+
+/// Takes the value following `flag` on the command line.
+fn option_value<'a>(args: &mut impl Iterator<Item = &'a String>, flag: &str) -> Result<&'a String> {
+    args.next()
+        .ok_or_else(|| anyhow::anyhow!("Missing value for {}", flag))
+}
+
+/// Takes the value following `flag` on the command line and parses it.
+fn parse_value<'a, T: FromStr>(
+    args: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<T> {
+    let value = option_value(args, flag)?;
+    value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid value for {}: {}", flag, value))
+}
+
+/// Parse command line arguments that support shorthand notation. Each number is how many cards a
+/// participant sends and receives; `S:R` sends `S` and receives `R` instead.
+/// Examples:
+/// - "3" -> [3]
+/// - "3x4" -> [3, 3, 3, 3]
+/// - "1x3 2x3 3x4" -> [1, 1, 1, 2, 2, 2, 3, 3, 3, 3]
+/// - "5:2x2 3" -> [5:2, 5:2, 3]
+fn parse_shorthand_args(args: &[String]) -> Result<Vec<(u32, u32)>> {
+    let mut result = Vec::new();
+
+    for arg in args {
+        if let Some((num_str, count_str)) = arg.split_once('x') {
+            // Parse "NxM" format
+            let num = parse_send_receive(num_str)?;
+            let count: usize = count_str
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid count in {}: {}", arg, count_str))?;
+
+            result.extend(std::iter::repeat_n(num, count));
+        } else {
+            // Parse single number
+            result.push(parse_send_receive(arg)?);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parses `N` as sending and receiving `N` cards, and `S:R` as sending `S` and receiving `R`.
+fn parse_send_receive(arg: &str) -> Result<(u32, u32)> {
+    let parse = |num_str: &str| -> Result<u32> {
+        num_str
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid card count: {}", num_str))
+    };
+    match arg.split_once(':') {
+        Some((send, receive)) => Ok((parse(send)?, parse(receive)?)),
+        None => Ok((parse(arg)?, parse(arg)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn shorthand_expands_counts_and_ranges() {
+        assert_eq!(
+            parse_shorthand_args(&args("2x2 3 5:2x2")).unwrap(),
+            vec![(2, 2), (2, 2), (3, 3), (5, 2), (5, 2)]
+        );
+    }
+
+    #[test]
+    fn shorthand_rejects_bad_numbers() {
+        for line in ["abc", "3xq", "2:z", "-1"] {
+            assert!(parse_shorthand_args(&args(line)).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn unknown_options_are_rejected_with_usage() {
+        let error = parse_options(&args("--time-limt 10 3x4")).err().unwrap();
+        assert!(error.to_string().contains("Unknown option: --time-limt"));
+        assert!(error.to_string().contains("Usage:"));
+    }
 }
@@ -0,0 +1,389 @@
+use std::collections::VecDeque;
+
+use russcip::{
+    Conshdlr, ConshdlrResult, Model, ProblemOrSolving, SCIPConshdlr, Solution, Solving, Variable,
+};
+
+use crate::solver::Pair;
+
+/// The shortest directed cycle through each participant that is shorter than `min_length`, as
+/// the participants along it in order. `used(i, j)` says whether `i` sends to `j`. Each cycle is
+/// returned once, however many of its participants it was found from.
+pub fn short_cycles(
+    n: usize,
+    used: impl Fn(usize, usize) -> bool,
+    min_length: usize,
+) -> Vec<Vec<usize>> {
+    let successors: Vec<Vec<usize>> = (0..n)
+        .map(|i| (0..n).filter(|&j| i != j && used(i, j)).collect())
+        .collect();
+
+    let mut cycles: Vec<Vec<usize>> = Vec::new();
+    for start in 0..n {
+        // Breadth-first search from start, until we find an edge back to it.
+        let mut parent = vec![None; n];
+        let mut depth = vec![0; n];
+        let mut queue = VecDeque::from([start]);
+        let mut closing = None;
+        while let Some(i) = queue.pop_front() {
+            if depth[i] + 1 >= min_length {
+                break;
+            }
+            if successors[i].contains(&start) {
+                closing = Some(i);
+                break;
+            }
+            for &j in &successors[i] {
+                if j != start && parent[j].is_none() {
+                    parent[j] = Some(i);
+                    depth[j] = depth[i] + 1;
+                    queue.push_back(j);
+                }
+            }
+        }
+
+        // Walk back from the closing edge to start.
+        let Some(mut i) = closing else { continue };
+        let mut cycle = vec![i];
+        while let Some(previous) = parent[i] {
+            cycle.push(previous);
+            i = previous;
+        }
+        cycle.reverse();
+
+        // Rotate so the cycle starts at its smallest participant, to spot duplicates.
+        let smallest = (0..cycle.len()).min_by_key(|&k| cycle[k]).unwrap();
+        cycle.rotate_left(smallest);
+        if !cycles.contains(&cycle) {
+            cycles.push(cycle);
+        }
+    }
+    cycles
+}
+
+/// The strongly connected components of the exchange graph: groups of participants who can all
+/// reach each other by following sent cards. `used(i, j)` says whether `i` sends to `j`. Everyone
+/// is in exactly one component, possibly on their own.
+pub fn strongly_connected_components(
+    n: usize,
+    used: impl Fn(usize, usize) -> bool,
+) -> Vec<Vec<usize>> {
+    let successors: Vec<Vec<usize>> = (0..n)
+        .map(|i| (0..n).filter(|&j| i != j && used(i, j)).collect())
+        .collect();
+    let predecessors: Vec<Vec<usize>> = (0..n)
+        .map(|j| (0..n).filter(|&i| i != j && used(i, j)).collect())
+        .collect();
+
+    // Kosaraju: order participants by when a depth-first search finishes with them...
+    let mut visited = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    for start in 0..n {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut stack = vec![(start, 0)];
+        while let Some((i, next)) = stack.pop() {
+            if let Some(&j) = successors[i].get(next) {
+                stack.push((i, next + 1));
+                if !visited[j] {
+                    visited[j] = true;
+                    stack.push((j, 0));
+                }
+            } else {
+                order.push(i);
+            }
+        }
+    }
+
+    // ...then collect what reaches each of them, latest finished first.
+    let mut component = vec![None; n];
+    let mut components = Vec::new();
+    for &start in order.iter().rev() {
+        if component[start].is_some() {
+            continue;
+        }
+        let mut members = Vec::new();
+        let mut stack = vec![start];
+        component[start] = Some(components.len());
+        while let Some(j) = stack.pop() {
+            members.push(j);
+            for &i in &predecessors[j] {
+                if component[i].is_none() {
+                    component[i] = Some(components.len());
+                    stack.push(i);
+                }
+            }
+        }
+        members.sort();
+        components.push(members);
+    }
+    components
+}
+
+/// Lazily forbids exchange cycles shorter than `min_length`: whenever a solution has one, adds a
+/// cut saying that not every pair along it can be used.
+pub(crate) struct ShortCycleConshdlr {
+    pub(crate) x: Vec<Vec<Pair>>,
+    pub(crate) min_length: usize,
+}
+
+impl ShortCycleConshdlr {
+    fn cycles(&self, value: impl Fn(&Pair) -> f64) -> Vec<Vec<usize>> {
+        short_cycles(
+            self.x.len(),
+            |i, j| value(&self.x[i][j]) > 0.5,
+            self.min_length,
+        )
+    }
+}
+
+impl Conshdlr for ShortCycleConshdlr {
+    fn check(
+        &mut self,
+        _model: Model<Solving>,
+        _conshdlr: SCIPConshdlr,
+        solution: &Solution,
+    ) -> bool {
+        self.cycles(|pair| pair.value(solution)).is_empty()
+    }
+
+    fn enforce(&mut self, mut model: Model<Solving>, _conshdlr: SCIPConshdlr) -> ConshdlrResult {
+        let cycles = self.cycles(|pair| pair.current_value(&model));
+        if cycles.is_empty() {
+            return ConshdlrResult::Feasible;
+        }
+
+        for cycle in &cycles {
+            // At most len - 1 of the pairs along the cycle are used; fixed ones count up front.
+            let mut vars = Vec::new();
+            let mut rhs = cycle.len() as f64 - 1.;
+            for (k, &i) in cycle.iter().enumerate() {
+                let j = cycle[(k + 1) % cycle.len()];
+                match &self.x[i][j] {
+                    Pair::Free(var) => vars.push(var),
+                    Pair::Fixed(true) => rhs -= 1.,
+                    Pair::Fixed(false) => {}
+                }
+            }
+            if vars.is_empty() {
+                return ConshdlrResult::CutOff;
+            }
+            let coefs = vec![1.; vars.len()];
+            model.add_cons(vars, &coefs, -f64::INFINITY, rhs, "short_cycle");
+        }
+        ConshdlrResult::ConsAdded
+    }
+}
+
+/// The pairs from `members` to everyone else, or from everyone else to `members` if not
+/// `outgoing`.
+fn crossing_pairs<'a>(
+    x: &'a [Vec<Pair>],
+    members: &'a [usize],
+    outgoing: bool,
+) -> impl Iterator<Item = &'a Pair> + 'a {
+    members.iter().flat_map(move |&i| {
+        (0..x.len())
+            .filter(move |j| !members.contains(j))
+            .map(move |j| if outgoing { &x[i][j] } else { &x[j][i] })
+    })
+}
+
+/// Adds a cut saying that at least one of `pairs` or `vars` is used, and returns whether it could.
+/// Fails only if every pair is fixed and unused and there are no `vars`, so nothing can satisfy
+/// it.
+fn add_covering_cut<'a>(
+    model: &mut Model<Solving>,
+    pairs: impl Iterator<Item = &'a Pair>,
+    vars: impl Iterator<Item = &'a Variable>,
+    name: &str,
+) -> bool {
+    let mut cut_vars = Vec::new();
+    let mut lhs = 1.;
+    for pair in pairs {
+        match pair {
+            Pair::Free(var) => cut_vars.push(var),
+            Pair::Fixed(true) => lhs -= 1.,
+            Pair::Fixed(false) => {}
+        }
+    }
+    cut_vars.extend(vars);
+    if cut_vars.is_empty() {
+        return lhs <= 0.;
+    }
+    let coefs = vec![1.; cut_vars.len()];
+    model.add_cons(cut_vars, &coefs, lhs, f64::INFINITY, name);
+    true
+}
+
+/// Lazily eliminates subtours in single-ring mode. Every group of participants that only send
+/// cards among themselves must contain a ring leader, so the number of leaders bounds the number
+/// of rings from above.
+pub(crate) struct SubtourConshdlr {
+    pub(crate) x: Vec<Vec<Pair>>,
+    /// leaders[i] is 1 if person i leads their ring. Only present for people taking part.
+    pub(crate) leaders: Vec<Option<Variable>>,
+}
+
+impl SubtourConshdlr {
+    /// Groups of participants without a leader that send no cards outside the group.
+    fn leaderless_subtours(
+        &self,
+        value: impl Fn(&Pair) -> f64,
+        leader: impl Fn(&Variable) -> f64,
+    ) -> Vec<Vec<usize>> {
+        strongly_connected_components(self.x.len(), |i, j| value(&self.x[i][j]) > 0.5)
+            .into_iter()
+            .filter(|members| members.len() > 1)
+            .filter(|members| {
+                let leaving: f64 = crossing_pairs(&self.x, members, true).map(&value).sum();
+                let leaders: f64 = members
+                    .iter()
+                    .filter_map(|&i| self.leaders[i].as_ref())
+                    .map(&leader)
+                    .sum();
+                leaving + leaders < 0.5
+            })
+            .collect()
+    }
+}
+
+impl Conshdlr for SubtourConshdlr {
+    fn check(
+        &mut self,
+        _model: Model<Solving>,
+        _conshdlr: SCIPConshdlr,
+        solution: &Solution,
+    ) -> bool {
+        self.leaderless_subtours(|pair| pair.value(solution), |var| solution.val(var))
+            .is_empty()
+    }
+
+    fn enforce(&mut self, mut model: Model<Solving>, _conshdlr: SCIPConshdlr) -> ConshdlrResult {
+        let subtours = self.leaderless_subtours(
+            |pair| pair.current_value(&model),
+            |var| model.current_val(var),
+        );
+        if subtours.is_empty() {
+            return ConshdlrResult::Feasible;
+        }
+
+        for members in &subtours {
+            // Some card leaves the group, or someone in it leads a ring.
+            let leaders = members.iter().filter_map(|&i| self.leaders[i].as_ref());
+            let pairs = crossing_pairs(&self.x, members, true);
+            if !add_covering_cut(&mut model, pairs, leaders, "subtour") {
+                return ConshdlrResult::CutOff;
+            }
+        }
+        ConshdlrResult::ConsAdded
+    }
+}
+
+/// Lazily requires everyone who sends and receives cards to be able to reach everyone else who
+/// does through the chain of sent cards. Whenever a solution splits into several strongly connected groups,
+/// adds cuts saying that some card must leave, and some card must enter, each of them.
+pub(crate) struct StrongConnectivityConshdlr {
+    pub(crate) x: Vec<Vec<Pair>>,
+    /// Whether each participant can both send and receive cards, and so must be connected.
+    pub(crate) active: Vec<bool>,
+}
+
+impl StrongConnectivityConshdlr {
+    /// The strongly connected components with someone active in them, if there is more than one.
+    fn disconnected_components(&self, value: impl Fn(&Pair) -> f64) -> Vec<Vec<usize>> {
+        let components: Vec<Vec<usize>> =
+            strongly_connected_components(self.x.len(), |i, j| value(&self.x[i][j]) > 0.5)
+                .into_iter()
+                .filter(|members| members.iter().any(|&i| self.active[i]))
+                .collect();
+        if components.len() > 1 {
+            components
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+impl Conshdlr for StrongConnectivityConshdlr {
+    fn check(
+        &mut self,
+        _model: Model<Solving>,
+        _conshdlr: SCIPConshdlr,
+        solution: &Solution,
+    ) -> bool {
+        self.disconnected_components(|pair| pair.value(solution))
+            .is_empty()
+    }
+
+    fn enforce(&mut self, mut model: Model<Solving>, _conshdlr: SCIPConshdlr) -> ConshdlrResult {
+        let components = self.disconnected_components(|pair| pair.current_value(&model));
+        if components.is_empty() {
+            return ConshdlrResult::Feasible;
+        }
+
+        for members in &components {
+            for outgoing in [true, false] {
+                let pairs = crossing_pairs(&self.x, members, outgoing);
+                if !add_covering_cut(&mut model, pairs, std::iter::empty(), "strong_connectivity") {
+                    return ConshdlrResult::CutOff;
+                }
+            }
+        }
+        ConshdlrResult::ConsAdded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `used(i, j)` for exactly the given pairs.
+    fn using(pairs: &[(usize, usize)]) -> impl Fn(usize, usize) -> bool + '_ {
+        move |i, j| pairs.contains(&(i, j))
+    }
+
+    #[test]
+    fn short_cycles_are_found_once_from_their_smallest_member() {
+        // 0 -> 1 -> 2 -> 0 and 3 -> 4 -> 5 -> 6 -> 3.
+        let pairs = [(1, 2), (2, 0), (0, 1), (3, 4), (4, 5), (5, 6), (6, 3)];
+        assert!(short_cycles(7, using(&pairs), 3).is_empty());
+        assert_eq!(short_cycles(7, using(&pairs), 4), vec![vec![0, 1, 2]]);
+        assert_eq!(
+            short_cycles(7, using(&pairs), 5),
+            vec![vec![0, 1, 2], vec![3, 4, 5, 6]]
+        );
+    }
+
+    #[test]
+    fn short_cycles_include_mutual_exchanges() {
+        let pairs = [(0, 1), (1, 0), (1, 2)];
+        assert_eq!(short_cycles(3, using(&pairs), 3), vec![vec![0, 1]]);
+    }
+
+    #[test]
+    fn short_cycles_ignore_self_pairs() {
+        assert!(short_cycles(2, using(&[(0, 0)]), 3).is_empty());
+    }
+
+    #[test]
+    fn components_split_rings_and_chains() {
+        // A ring 0 -> 1 -> 2 -> 0, a chain 3 -> 4 and 5 on their own.
+        let pairs = [(0, 1), (1, 2), (2, 0), (3, 4)];
+        let mut components = strongly_connected_components(6, using(&pairs));
+        components.sort();
+        assert_eq!(components, vec![vec![0, 1, 2], vec![3], vec![4], vec![5]]);
+    }
+
+    #[test]
+    fn components_merge_rings_that_reach_each_other() {
+        // Two rings joined both ways through 2 and 3.
+        let pairs = [(0, 1), (1, 2), (2, 0), (2, 3), (3, 4), (4, 2)];
+        assert_eq!(
+            strongly_connected_components(5, using(&pairs)),
+            vec![vec![0, 1, 2, 3, 4]]
+        );
+    }
+}
@@ -0,0 +1,545 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+
+use russcip::{HeurResult, HeurTiming, Heuristic, Model, ProblemOrSolving, Solving, WithSolutions};
+
+use crate::solver::{HelperVars, Pair};
+use crate::{HistoryMode, InternationalMix, PairingProblem};
+
+/// LP values at or below this count as 0.
+const ROUNDING_EPSILON: f64 = 1e-6;
+
+/// A quick pairing built without SCIP, to give the solver a head start.
+///
+/// Pairs decided in advance come first. Then, round by round, everyone who can still send and
+/// receive, most cards requested first, is strung into a ring where each person sends to the
+/// best-weighted partner still allowed; rings keep everyone's sent and received counts equal.
+/// People who need not be balanced then fill up their remaining cards one at a time.
+///
+/// The result respects card counts, exclusions, forbidden and mutual pairs and international
+/// caps, but not necessarily minimums or cycle and connectivity rules: the solver checks it, and
+/// ignores it if it breaks any of them.
+pub fn greedy_pairings(problem: &PairingProblem) -> Vec<(usize, usize)> {
+    let rules = PairRules::new(problem);
+    let mut greedy = Greedy::new(problem, &rules);
+    greedy.finish();
+    greedy.pairings
+}
+
+/// Rounds a fractional pairing, such as SCIP's LP relaxation, where `value(i, j)` is how much
+/// of a card `i` sends to `j`.
+///
+/// Pairs are taken in order of decreasing value wherever [`greedy_pairings`] would allow them,
+/// which rules out mutual exchanges. Balanced people who end up sending more cards than they
+/// receive, or the reverse, then give up their lowest valued pairs until they are even, and the
+/// cards this frees up are handed out as in [`greedy_pairings`].
+pub fn round_pairings(
+    problem: &PairingProblem,
+    value: impl Fn(usize, usize) -> f64,
+) -> Vec<(usize, usize)> {
+    round_with_rules(problem, &PairRules::new(problem), value)
+}
+
+fn round_with_rules(
+    problem: &PairingProblem,
+    rules: &PairRules,
+    value: impl Fn(usize, usize) -> f64,
+) -> Vec<(usize, usize)> {
+    let mut greedy = Greedy::new(problem, rules);
+    let n = problem.num_participants();
+    let mut candidates: Vec<(usize, usize)> = (0..n)
+        .flat_map(|i| (0..n).map(move |j| (i, j)))
+        .filter(|&(i, j)| value(i, j) > ROUNDING_EPSILON)
+        .collect();
+    candidates.sort_by(|&(a, b), &(c, d)| value(c, d).total_cmp(&value(a, b)));
+    for (i, j) in candidates {
+        if greedy.can_use(i, j) {
+            greedy.use_pair(i, j);
+        }
+    }
+    greedy.balance(&value);
+    greedy.finish();
+    greedy.pairings
+}
+
+/// A SCIP primal heuristic that rounds the LP relaxation at the current node into a pairing with
+/// [`round_pairings`].
+pub(crate) struct RoundingHeuristic {
+    problem: PairingProblem,
+    rules: PairRules,
+    x: Vec<Vec<Pair>>,
+    helpers: HelperVars,
+}
+
+impl RoundingHeuristic {
+    pub(crate) fn new(problem: &PairingProblem, x: Vec<Vec<Pair>>, helpers: HelperVars) -> Self {
+        RoundingHeuristic {
+            problem: problem.clone(),
+            rules: PairRules::new(problem),
+            x,
+            helpers,
+        }
+    }
+}
+
+impl Heuristic for RoundingHeuristic {
+    fn execute(
+        &mut self,
+        model: Model<Solving>,
+        _timing: HeurTiming,
+        _node_infeasible: bool,
+    ) -> HeurResult {
+        let values: Vec<Vec<f64>> = self
+            .x
+            .iter()
+            .map(|row| row.iter().map(|pair| pair.current_value(&model)).collect())
+            .collect();
+        let pairings = round_with_rules(&self.problem, &self.rules, |i, j| values[i][j]);
+        if pairings.is_empty() {
+            return HeurResult::NoSolFound;
+        }
+
+        // Presolve may have fixed or merged some of the variables, so build the pairing in terms
+        // of the original ones and let SCIP carry it over.
+        let sol = model.create_orig_sol();
+        self.helpers
+            .set_solution(&sol, &self.problem, &self.x, &pairings);
+        let improves = model
+            .best_sol()
+            .is_none_or(|best| sol.obj_val() > best.obj_val());
+        if improves && model.add_sol(sol).is_ok() {
+            HeurResult::FoundSol
+        } else {
+            HeurResult::NoSolFound
+        }
+    }
+}
+
+/// What the greedy pairing needs to know about each pair, worked out once per problem since the
+/// rounding heuristic runs many times.
+struct PairRules {
+    /// allowed[i][j] is whether `i` may send to `j` at all, whatever else is used.
+    allowed: Vec<Vec<bool>>,
+    /// international[i][j] is whether a card from `i` to `j` crosses a border.
+    international: Vec<Vec<bool>>,
+    /// weight[i][j] is the objective weight of `i` sending to `j`.
+    weight: Vec<Vec<f64>>,
+    /// Pairs decided in advance: fixed, or required.
+    pinned: Vec<(usize, usize)>,
+}
+
+impl PairRules {
+    fn new(problem: &PairingProblem) -> Self {
+        let n = problem.num_participants();
+        let participants = problem.participants();
+        let mut pinned = Vec::new();
+        let mut allowed = vec![vec![false; n]; n];
+        let mut international = vec![vec![false; n]; n];
+        let mut weight = vec![vec![0.; n]; n];
+        for i in 0..n {
+            for j in 0..n {
+                let fixed = problem.fixed_pair(i, j);
+                if fixed == Some(true) {
+                    pinned.push((i, j));
+                }
+                international[i][j] = problem.is_international(i, j);
+                weight[i][j] = problem.objective_weight(i, j);
+                allowed[i][j] = i != j
+                    && fixed != Some(false)
+                    && !problem.is_forbidden(i, j)
+                    && !(problem.history_mode() == HistoryMode::Forbid && problem.is_repeat(i, j))
+                    && !(international[i][j]
+                        && (participants[i].max_international == Some(0)
+                            || participants[j].international_mix
+                                == InternationalMix::DomesticOnly));
+            }
+        }
+        for &pair in problem.required_pairs() {
+            if !pinned.contains(&pair) {
+                pinned.push(pair);
+            }
+        }
+        PairRules {
+            allowed,
+            international,
+            weight,
+            pinned,
+        }
+    }
+}
+
+/// The pairing built so far by [`greedy_pairings`] or [`round_pairings`].
+struct Greedy<'a> {
+    problem: &'a PairingProblem,
+    rules: &'a PairRules,
+    /// Pairs used so far; the first `pinned` of them are fixed or required, and stay.
+    pairings: Vec<(usize, usize)>,
+    pinned: usize,
+    /// used[i][j] is whether `i` sends to `j` so far.
+    used: Vec<Vec<bool>>,
+    sent: Vec<u32>,
+    received: Vec<u32>,
+    sent_abroad: Vec<u32>,
+}
+
+impl<'a> Greedy<'a> {
+    /// Starts from the pairs decided in advance.
+    fn new(problem: &'a PairingProblem, rules: &'a PairRules) -> Self {
+        let n = problem.num_participants();
+        let mut greedy = Greedy {
+            problem,
+            rules,
+            pairings: Vec::new(),
+            pinned: 0,
+            used: vec![vec![false; n]; n],
+            sent: vec![0; n],
+            received: vec![0; n],
+            sent_abroad: vec![0; n],
+        };
+        for &(i, j) in &rules.pinned {
+            greedy.use_pair(i, j);
+        }
+        greedy.pinned = greedy.pairings.len();
+        greedy
+    }
+
+    /// Hands out the cards left: in rings among people who can still send and receive, then one
+    /// at a time for people who need not be balanced.
+    fn finish(&mut self) {
+        self.close_chains();
+        loop {
+            let ring = self.ring();
+            if ring.is_empty() {
+                break;
+            }
+            for (k, &i) in ring.iter().enumerate() {
+                self.use_pair(i, ring[(k + 1) % ring.len()]);
+            }
+            // Everyone sends exactly one card in single-ring mode.
+            if self.problem.is_single_ring() {
+                return;
+            }
+        }
+        while self.fill_round() {}
+    }
+
+    fn use_pair(&mut self, i: usize, j: usize) {
+        self.pairings.push((i, j));
+        self.used[i][j] = true;
+        self.sent[i] += 1;
+        self.received[j] += 1;
+        if self.rules.international[i][j] {
+            self.sent_abroad[i] += 1;
+        }
+    }
+
+    fn remove_pair(&mut self, k: usize) {
+        let (i, j) = self.pairings.remove(k);
+        self.used[i][j] = false;
+        self.sent[i] -= 1;
+        self.received[j] -= 1;
+        if self.rules.international[i][j] {
+            self.sent_abroad[i] -= 1;
+        }
+    }
+
+    /// Drops the lowest valued pairs of balanced people until each sends as many cards as they
+    /// receive, or only pinned pairs are left to drop.
+    fn balance(&mut self, value: impl Fn(usize, usize) -> f64) {
+        let participants = self.problem.participants();
+        while let Some(i) = (0..participants.len())
+            .find(|&i| participants[i].is_balanced() && self.sent[i] != self.received[i])
+        {
+            let sends_more = self.sent[i] > self.received[i];
+            let lowest = (self.pinned..self.pairings.len())
+                .filter(|&k| {
+                    let (sender, receiver) = self.pairings[k];
+                    if sends_more {
+                        sender == i
+                    } else {
+                        receiver == i
+                    }
+                })
+                .min_by(|&a, &b| {
+                    let (pa, pb) = (self.pairings[a], self.pairings[b]);
+                    value(pa.0, pa.1).total_cmp(&value(pb.0, pb.1))
+                });
+            match lowest {
+                Some(k) => self.remove_pair(k),
+                None => return,
+            }
+        }
+    }
+
+    fn can_send(&self, i: usize) -> bool {
+        self.sent[i] < self.problem.participants()[i].send_range().max
+    }
+
+    fn can_receive(&self, j: usize) -> bool {
+        self.received[j] < self.problem.participants()[j].receive_range().max
+    }
+
+    /// Whether `i` may send one more card to `j`.
+    fn can_use(&self, i: usize, j: usize) -> bool {
+        self.rules.allowed[i][j]
+            && self.can_send(i)
+            && self.can_receive(j)
+            && !self.used[i][j]
+            && !self.used[j][i]
+            && (!self.rules.international[i][j]
+                || self.problem.participants()[i]
+                    .max_international
+                    .is_none_or(|max| self.sent_abroad[i] < max))
+    }
+
+    /// Evens out balanced people left sending more than they receive, or the reverse, by pairs
+    /// that could not be dropped: each chain of such pairs is led back from its end to its start
+    /// along the shortest path allowed, through people who can send and receive one more card.
+    fn close_chains(&mut self) {
+        let participants = self.problem.participants();
+        let n = participants.len();
+        let excess = |greedy: &Self, i: usize| {
+            participants[i]
+                .is_balanced()
+                .then(|| i64::from(greedy.received[i]) - i64::from(greedy.sent[i]))
+        };
+        let mut stuck = vec![false; n];
+        while let Some(end) = (0..n).find(|&i| !stuck[i] && excess(self, i).unwrap_or(0) > 0) {
+            // Breadth-first search from the end of the chain to anyone with a card to take back.
+            let mut parent = vec![None; n];
+            let mut queue = VecDeque::from([end]);
+            let mut start = None;
+            while let Some(i) = queue.pop_front() {
+                let next: Vec<usize> = (0..n)
+                    .filter(|&j| j != end && parent[j].is_none() && self.can_use(i, j))
+                    .collect();
+                for j in next {
+                    parent[j] = Some(i);
+                    if excess(self, j).unwrap_or(0) < 0 {
+                        start = Some(j);
+                        break;
+                    }
+                    if self.can_send(j) && excess(self, j).is_none_or(|e| e == 0) {
+                        queue.push_back(j);
+                    }
+                }
+                if start.is_some() {
+                    break;
+                }
+            }
+
+            let Some(mut j) = start else {
+                stuck[end] = true;
+                continue;
+            };
+            let mut path = Vec::new();
+            while let Some(i) = parent[j] {
+                path.push((i, j));
+                j = i;
+            }
+            for (i, j) in path {
+                self.use_pair(i, j);
+            }
+        }
+    }
+
+    /// A ring through as many people with cards left to send and receive as possible, or none if
+    /// no ring of the minimum cycle length fits.
+    fn ring(&self) -> Vec<usize> {
+        let participants = self.problem.participants();
+        let mut candidates: Vec<usize> = (0..participants.len())
+            .filter(|&i| self.can_send(i) && self.can_receive(i))
+            .collect();
+        candidates.sort_by_key(|&i| Reverse(participants[i].send_range().max));
+        let Some((&first, rest)) = candidates.split_first() else {
+            return Vec::new();
+        };
+
+        // Walk from the first person to the best partner left each time...
+        let mut ring = vec![first];
+        let mut left = rest.to_vec();
+        loop {
+            let last = *ring.last().unwrap();
+            let next = left
+                .iter()
+                .enumerate()
+                .filter(|&(_, &j)| self.can_use(last, j))
+                .max_by(|&(a, &i), &(b, &j)| {
+                    let weight = &self.rules.weight[last];
+                    // Ties go to whoever requested more, who comes first.
+                    weight[i].total_cmp(&weight[j]).then(b.cmp(&a))
+                })
+                .map(|(k, _)| k);
+            let Some(k) = next else {
+                break;
+            };
+            ring.push(left.remove(k));
+        }
+
+        // ...then drop people off the end until it closes back to the start.
+        let min_length = self.problem.min_cycle_length();
+        while ring.len() >= min_length {
+            if self.can_use(*ring.last().unwrap(), first) {
+                return ring;
+            }
+            ring.pop();
+        }
+        Vec::new()
+    }
+
+    /// Has everyone who need not be balanced send one more card, to whoever is furthest from
+    /// what they asked for. Returns whether any card was added.
+    fn fill_round(&mut self) -> bool {
+        let participants = self.problem.participants();
+        let mut senders: Vec<usize> = (0..participants.len())
+            .filter(|&i| !participants[i].is_balanced() && self.can_send(i))
+            .collect();
+        senders.sort_by_key(|&i| Reverse(participants[i].send_range().max - self.sent[i]));
+
+        let mut added = false;
+        for i in senders {
+            let receiver = (0..participants.len())
+                .filter(|&j| !participants[j].is_balanced() && self.can_use(i, j))
+                .max_by(|&a, &b| {
+                    let need = |j: usize| participants[j].receive_range().max - self.received[j];
+                    let weight = &self.rules.weight[i];
+                    need(a).cmp(&need(b)).then(weight[a].total_cmp(&weight[b]))
+                });
+            if let Some(j) = receiver {
+                self.use_pair(i, j);
+                added = true;
+            }
+        }
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CardRange, Participant};
+
+    /// Checks the rules that every greedy or rounded pairing keeps.
+    fn assert_valid(problem: &PairingProblem, pairings: &[(usize, usize)]) {
+        for (k, &(i, j)) in pairings.iter().enumerate() {
+            assert_ne!(i, j, "{} sends to themself", i);
+            assert!(!problem.is_forbidden(i, j), "forbidden pair {} -> {}", i, j);
+            assert!(
+                !pairings.contains(&(j, i)),
+                "mutual exchange {} <-> {}",
+                i,
+                j
+            );
+            assert!(!pairings[..k].contains(&(i, j)), "duplicate {} -> {}", i, j);
+        }
+        for (i, participant) in problem.participants().iter().enumerate() {
+            let sent = pairings.iter().filter(|&&(s, _)| s == i).count() as u32;
+            let received = pairings.iter().filter(|&&(_, r)| r == i).count() as u32;
+            assert!(sent <= participant.send_range().max, "{} sends {}", i, sent);
+            assert!(
+                received <= participant.receive_range().max,
+                "{} receives {}",
+                i,
+                received
+            );
+            if participant.is_balanced() {
+                assert_eq!(sent, received, "{} is unbalanced", i);
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_gives_everyone_a_card() {
+        let problem = PairingProblem::from_card_counts(&[3; 5]);
+        let pairings = greedy_pairings(&problem);
+        assert_valid(&problem, &pairings);
+        for i in 0..5 {
+            assert!(pairings.iter().any(|&(s, _)| s == i), "{} sends nothing", i);
+        }
+    }
+
+    #[test]
+    fn greedy_keeps_households_apart() {
+        let participants = (0..6)
+            .map(|i| Participant::new(format!("P{}", i), 2).group(format!("H{}", i / 2)))
+            .collect();
+        let problem = PairingProblem::from_participants(participants);
+        let pairings = greedy_pairings(&problem);
+        assert_valid(&problem, &pairings);
+        assert!(!pairings.is_empty());
+    }
+
+    #[test]
+    fn greedy_starts_from_required_pairs() {
+        let problem = PairingProblem::from_card_counts(&[2; 4]).require_pair(3, 0);
+        let pairings = greedy_pairings(&problem);
+        assert_valid(&problem, &pairings);
+        assert!(pairings.contains(&(3, 0)));
+    }
+
+    #[test]
+    fn greedy_respects_international_limits() {
+        let participants = vec![
+            Participant::new("A", 2).country("NL").max_international(0),
+            Participant::new("B", 2).country("DE"),
+            Participant::new("C", 2)
+                .country("NL")
+                .international_mix(InternationalMix::DomesticOnly),
+            Participant::new("D", 2).country("DE"),
+            Participant::new("E", 2).country("NL"),
+        ];
+        let problem = PairingProblem::from_participants(participants);
+        let pairings = greedy_pairings(&problem);
+        assert_valid(&problem, &pairings);
+        for &(i, j) in &pairings {
+            if problem.is_international(i, j) {
+                assert_ne!(i, 0, "A sends abroad");
+                assert_ne!(j, 2, "C gets a card from abroad");
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_fills_up_unbalanced_senders() {
+        let participants = vec![
+            Participant::new("A", 2).sends(CardRange::new(2, 3)),
+            Participant::new("B", 2).role(crate::Role::ReceiveOnly),
+            Participant::new("C", 2).role(crate::Role::ReceiveOnly),
+            Participant::new("D", 2).role(crate::Role::ReceiveOnly),
+        ];
+        let problem = PairingProblem::from_participants(participants);
+        let pairings = greedy_pairings(&problem);
+        assert_valid(&problem, &pairings);
+        assert_eq!(pairings.len(), 3);
+    }
+
+    #[test]
+    fn rounding_follows_the_lp_values() {
+        let problem = PairingProblem::from_card_counts(&[1; 4]);
+        // Mostly the ring 0 -> 1 -> 2 -> 3 -> 0, with a little of everything else.
+        let ring = [(0, 1), (1, 2), (2, 3), (3, 0)];
+        let value = |i: usize, j: usize| if ring.contains(&(i, j)) { 0.9 } else { 0.1 };
+        let mut pairings = round_pairings(&problem, value);
+        assert_valid(&problem, &pairings);
+        pairings.sort();
+        assert_eq!(pairings, vec![(0, 1), (1, 2), (2, 3), (3, 0)]);
+    }
+
+    #[test]
+    fn rounding_rebalances_what_it_takes() {
+        // 0 would send two cards but receive one, unless rounding gives some back.
+        let problem = PairingProblem::from_card_counts(&[2, 1, 1, 2]);
+        let used = [(0, 1), (0, 2), (1, 3), (3, 0), (2, 3)];
+        let value = |i: usize, j: usize| if used.contains(&(i, j)) { 0.8 } else { 0. };
+        let pairings = round_pairings(&problem, value);
+        assert_valid(&problem, &pairings);
+        assert!(!pairings.is_empty());
+    }
+
+    #[test]
+    fn rounding_ignores_forbidden_pairs() {
+        let problem = PairingProblem::from_card_counts(&[1; 3]).forbid_pair(0, 1);
+        let pairings = round_pairings(&problem, |_, _| 0.5);
+        assert_valid(&problem, &pairings);
+    }
+}
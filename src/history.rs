@@ -0,0 +1,93 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::PairingSolution;
+
+/// One row of a pairings file.
+#[derive(Debug, Serialize, Deserialize)]
+struct PairingRecord {
+    sender: String,
+    receiver: String,
+}
+
+/// A pairing from a previous exchange, by participant name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PastPairing {
+    pub sender: String,
+    pub receiver: String,
+    /// How many exchanges ago this pairing was used: 1 for the most recent one.
+    pub age: u32,
+}
+
+/// How pairings from previous exchanges are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HistoryMode {
+    /// Past pairings may not be used again.
+    #[default]
+    Forbid,
+    /// Past pairings may be used again, but each repeat loses `penalty * decay^(age - 1)` weight.
+    Penalize { penalty: f64, decay: f64 },
+}
+
+/// The `penalty` of [`HistoryMode::Penalize`] when none is given.
+pub const DEFAULT_REPEAT_PENALTY: f64 = 0.5;
+
+/// The `decay` of [`HistoryMode::Penalize`] when none is given.
+pub const DEFAULT_REPEAT_DECAY: f64 = 0.5;
+
+/// What forbidding past pairings costs, from [`crate::PairingProblem::repeat_ban_cost`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RepeatBanCost {
+    /// How many more cards the best pairing sends when repeats are only penalized.
+    pub cards_lost: usize,
+    /// How many past pairings that pairing uses again.
+    pub repeats_needed: usize,
+}
+
+/// Writes the pairings in `solution` to a CSV file with `sender` and `receiver` name columns.
+pub fn save_pairings(solution: &PairingSolution, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let mut writer = csv::Writer::from_path(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    for &(i, j) in &solution.pairings {
+        writer.serialize(PairingRecord {
+            sender: solution.participants[i].name.clone(),
+            receiver: solution.participants[j].name.clone(),
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a pairings file written by [`save_pairings`], as `(sender, receiver)` names.
+pub fn load_pairings(path: impl AsRef<Path>) -> Result<Vec<(String, String)>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(file)
+        .deserialize()
+        .map(|record| record.map(|r: PairingRecord| (r.sender, r.receiver)))
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Reads the pairings files of previous exchanges, given oldest first, so the last file has an
+/// age of 1.
+pub fn load_history(paths: &[impl AsRef<Path>]) -> Result<Vec<PastPairing>> {
+    let mut history = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        let age = (paths.len() - i) as u32;
+        for (sender, receiver) in load_pairings(path)? {
+            history.push(PastPairing {
+                sender,
+                receiver,
+                age,
+            });
+        }
+    }
+    Ok(history)
+}
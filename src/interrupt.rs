@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use russcip::{Event, EventMask, Eventhdlr, Model, SCIPEventhdlr, Solving, ffi};
+
+/// Set when the current solve should stop and keep the best pairing found so far.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Set once [`interrupt_on_ctrl_c`] has installed its handler.
+static CATCHING_CTRL_C: AtomicBool = AtomicBool::new(false);
+
+/// Asks the solve in progress to stop as soon as it can. The best pairing found by then is
+/// returned as usual, with [`russcip::Status::UserInterrupt`] as its status. Safe to call from a
+/// [`crate::ProgressCallback`] or another thread.
+pub fn request_interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+pub fn interrupt_requested() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Clears an earlier request, at the start of a new solve.
+pub(crate) fn reset_interrupt() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Whether Ctrl-C is handled by [`interrupt_on_ctrl_c`] rather than by SCIP itself.
+pub(crate) fn catches_ctrl_c() -> bool {
+    CATCHING_CTRL_C.load(Ordering::SeqCst)
+}
+
+extern "C" fn handle_sigint(_signal: libc::c_int) {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        // A second Ctrl-C quits right away.
+        // SAFETY: signal and raise are async-signal-safe.
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::raise(libc::SIGINT);
+        }
+    }
+}
+
+/// Makes Ctrl-C call [`request_interrupt`] instead of killing the process, so that an interrupted
+/// solve still returns the best pairing it found. Pressing Ctrl-C a second time quits.
+pub fn interrupt_on_ctrl_c() -> Result<()> {
+    let handler = handle_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only touches an atomic, and calls async-signal-safe functions.
+    if unsafe { libc::signal(libc::SIGINT, handler) } == libc::SIG_ERR {
+        anyhow::bail!("Failed to install the Ctrl-C handler");
+    }
+    CATCHING_CTRL_C.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Passes an interrupt request on to SCIP, checking for one at every node and LP solve.
+pub(crate) struct InterruptEventhdlr;
+
+impl Eventhdlr for InterruptEventhdlr {
+    fn get_type(&self) -> EventMask {
+        EventMask::NODE_FOCUSED | EventMask::LP_SOLVED
+    }
+
+    fn execute(&mut self, model: Model<Solving>, _eventhdlr: SCIPEventhdlr, _event: Event) {
+        if interrupt_requested() {
+            // SAFETY: SCIP is solving, which is when it can be interrupted.
+            unsafe {
+                ffi::SCIPinterruptSolve(model.scip_ptr());
+            }
+        }
+    }
+}
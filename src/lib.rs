@@ -0,0 +1,44 @@
+//! Card exchange pairings, solved as an integer program with SCIP.
+//!
+//! Build a [`PairingProblem`] from the participants (for example loaded with [`load_roster`])
+//! and their requested card counts, then call [`PairingProblem::solve`] (or
+//! [`generate_pairings`]) to get a [`PairingSolution`].
+
+pub mod cycles;
+pub use cycles::*;
+
+pub mod heuristic;
+pub use heuristic::*;
+
+pub mod history;
+pub use history::*;
+
+pub mod interrupt;
+pub use interrupt::*;
+
+pub mod objective;
+pub use objective::*;
+
+pub mod preferences;
+pub use preferences::*;
+
+pub mod problem;
+pub use problem::*;
+
+pub mod progress;
+pub use progress::*;
+
+pub mod roster;
+pub use roster::*;
+
+pub mod shipping;
+pub use shipping::*;
+
+pub mod solution;
+pub use solution::*;
+
+pub mod solver;
+pub use solver::*;
+
+pub mod visualize;
+pub use visualize::*;
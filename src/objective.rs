@@ -0,0 +1,168 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// What the solver optimizes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ObjectiveMode {
+    /// Maximize the number of cards sent, then the total weight of those cards. Pair weights,
+    /// preferences, repeat penalties and change penalties therefore only choose among pairings
+    /// that send the most cards, and never cost a card; a [`Self::Lexicographic`] objective needs
+    /// a `cards` level ahead of `weight` for the same guarantee.
+    #[default]
+    MaxTotal,
+    /// Maximize the worst fulfillment ratio (cards received / cards requested) over all
+    /// participants, then break ties by the number of cards sent, and then by their weight.
+    MaxMinFairness,
+    /// Optimize each level in turn, in priority order, keeping every earlier level within its
+    /// tolerance of the optimum it reached.
+    Lexicographic(Vec<ObjectiveLevel>),
+}
+
+impl ObjectiveMode {
+    /// The objectives to optimize in turn, each keeping the optimum of the ones before.
+    pub fn levels(&self) -> Vec<ObjectiveLevel> {
+        match self {
+            ObjectiveMode::MaxTotal => vec![
+                ObjectiveLevel::new(Objective::TotalCards),
+                ObjectiveLevel::new(Objective::TotalWeight),
+            ],
+            ObjectiveMode::MaxMinFairness => vec![
+                ObjectiveLevel::new(Objective::MinFulfillment),
+                ObjectiveLevel::new(Objective::TotalCards),
+                ObjectiveLevel::new(Objective::TotalWeight),
+            ],
+            ObjectiveMode::Lexicographic(levels) => levels.clone(),
+        }
+    }
+}
+
+/// A single quantity to maximize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Objective {
+    /// The total objective weight of the cards sent: pair weights plus weighted preferences,
+    /// less repeat penalties.
+    TotalWeight,
+    /// The number of cards sent.
+    TotalCards,
+    /// The smallest fulfillment ratio of anyone who requested cards.
+    MinFulfillment,
+    /// The total preference score of the cards sent.
+    Preference,
+    /// The number of pairings reused from previous exchanges, negated so that fewer is better.
+    FewestRepeats,
+    /// The total shipping cost of the cards sent, negated so that cheaper is better.
+    ShippingCost,
+    /// The number of receive-only participants who get at least one card.
+    Coverage,
+    /// The number of rings in single-ring mode, negated so that fewer is better.
+    FewestRings,
+    /// Pseudo-random pair weights drawn from the problem's seed, which pick one optimum out of
+    /// many equally good ones.
+    Shuffle,
+}
+
+impl fmt::Display for Objective {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Objective::TotalWeight => "weight",
+            Objective::TotalCards => "cards",
+            Objective::MinFulfillment => "fairness",
+            Objective::Preference => "preference",
+            Objective::FewestRepeats => "repeats",
+            Objective::ShippingCost => "shipping",
+            Objective::Coverage => "coverage",
+            Objective::FewestRings => "rings",
+            Objective::Shuffle => "shuffle",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Objective {
+    type Err = anyhow::Error;
+
+    /// Parses the names used by [`Objective`]'s `Display` implementation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "weight" => Ok(Objective::TotalWeight),
+            "cards" => Ok(Objective::TotalCards),
+            "fairness" => Ok(Objective::MinFulfillment),
+            "preference" => Ok(Objective::Preference),
+            "repeats" => Ok(Objective::FewestRepeats),
+            "shipping" => Ok(Objective::ShippingCost),
+            "coverage" => Ok(Objective::Coverage),
+            "rings" => Ok(Objective::FewestRings),
+            "shuffle" => Ok(Objective::Shuffle),
+            other => anyhow::bail!("Unknown objective: {}", other),
+        }
+    }
+}
+
+/// One level of a lexicographic objective.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObjectiveLevel {
+    pub objective: Objective,
+    /// How far below its optimum this objective may fall while later levels are optimized.
+    pub tolerance: f64,
+}
+
+impl ObjectiveLevel {
+    /// A level whose optimum is kept exactly.
+    pub fn new(objective: Objective) -> Self {
+        Self {
+            objective,
+            tolerance: 0.,
+        }
+    }
+
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+}
+
+impl FromStr for ObjectiveLevel {
+    type Err = anyhow::Error;
+
+    /// Parses an objective name, optionally followed by `:TOLERANCE`, e.g. `cards` or `cards:1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((objective, tolerance)) => {
+                let tolerance: f64 = tolerance
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid tolerance: {}", tolerance))?;
+                if tolerance < 0. {
+                    anyhow::bail!("Tolerance must not be negative: {}", tolerance);
+                }
+                Ok(ObjectiveLevel::new(objective.parse()?).tolerance(tolerance))
+            }
+            None => Ok(ObjectiveLevel::new(s.parse()?)),
+        }
+    }
+}
+
+/// The value an objective reached when its level was optimized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObjectiveValue {
+    pub objective: Objective,
+    pub value: f64,
+}
+
+/// The [`Objective::Shuffle`] weight of `sender` sending to `receiver`, in `[0, 1)`. The same
+/// seed always gives the same weights.
+pub(crate) fn shuffle_weight(seed: u64, sender: usize, receiver: usize) -> f64 {
+    // Keyed on the seed and the pair.
+    let z = splitmix64(
+        seed.wrapping_add((sender as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+            .wrapping_add((receiver as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)),
+    );
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The SplitMix64 mixing function: spreads any change in `z` over all 64 bits of the result.
+pub fn splitmix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
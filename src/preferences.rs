@@ -0,0 +1,47 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// How much someone would like to send a card to someone else, by participant name.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Preference {
+    pub sender: String,
+    pub receiver: String,
+    /// How good a match the pair is, usually between 0 (no preference) and 1 (a great match).
+    /// Negative scores make the pair less likely.
+    pub score: f64,
+}
+
+/// The default [`crate::PairingProblem::preference_weight`].
+pub const DEFAULT_PREFERENCE_WEIGHT: f64 = 0.5;
+
+/// Reads a preferences CSV file with `sender`, `receiver` and `score` columns.
+pub fn load_preferences(path: impl AsRef<Path>) -> Result<Vec<Preference>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(file)
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// The preference score implied by interests: the fraction of the receiver's interests that the
+/// sender shares. Receivers without interests ("surprise me") score 0 with everyone.
+pub fn shared_interest_score(sender: &[String], receiver: &[String]) -> f64 {
+    if receiver.is_empty() {
+        return 0.;
+    }
+    let shared = receiver
+        .iter()
+        .filter(|interest| {
+            sender
+                .iter()
+                .any(|other| other.eq_ignore_ascii_case(interest))
+        })
+        .count();
+    shared as f64 / receiver.len() as f64
+}
@@ -0,0 +1,1244 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+
+use crate::{
+    DEFAULT_PREFERENCE_WEIGHT, DEFAULT_REPEAT_DECAY, DEFAULT_REPEAT_PENALTY, HistoryMode,
+    InternationalMix, ObjectiveMode, PairingSolution, PastPairing, Preference, Progress,
+    ProgressCallback, RepeatBanCost, ShippingCosts, generate_distinct_pairings, generate_pairings,
+    is_international, shared_interest_score,
+};
+
+/// Someone taking part in the card exchange.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Participant {
+    /// Unique name, used to identify the participant in input and output files.
+    pub name: String,
+    /// Where to send cards: an email or postal address. May be empty.
+    pub address: String,
+    /// The maximum number of cards this participant wants to send (and receive), unless
+    /// [`Self::sends`] or [`Self::receives`] say otherwise.
+    pub num_cards: u32,
+    /// How many cards they send, if not up to `num_cards`.
+    pub sends: Option<CardRange>,
+    /// How many cards they receive, if not up to `num_cards`.
+    pub receives: Option<CardRange>,
+    /// Whether they receive exactly as many cards as they send. Only applies to people who both
+    /// send and receive.
+    pub balanced: bool,
+    /// Whether they send, receive, or both.
+    pub role: Role,
+    /// Country or region code, for shipping costs.
+    pub country: Option<String>,
+    /// `(latitude, longitude)` in degrees, for distance-based shipping costs.
+    pub location: Option<(f64, f64)>,
+    /// The most cards they send to other countries.
+    pub max_international: Option<u32>,
+    /// Whether they want cards from abroad, or only from home.
+    pub international_mix: InternationalMix,
+    /// Household, team or other group; participants sharing a group never exchange cards.
+    pub group: Option<String>,
+    /// The fewest cards this participant must receive. Overrides the problem-wide minimum.
+    pub min_cards: Option<MinCards>,
+    /// Interests, languages and the like. Senders who share more of a receiver's interests are
+    /// preferred for them; leave empty for "surprise me".
+    pub interests: Vec<String>,
+}
+
+impl Participant {
+    pub fn new(name: impl Into<String>, num_cards: u32) -> Self {
+        Participant {
+            name: name.into(),
+            address: String::new(),
+            num_cards,
+            sends: None,
+            receives: None,
+            balanced: true,
+            role: Role::Both,
+            country: None,
+            location: None,
+            max_international: None,
+            international_mix: InternationalMix::Any,
+            group: None,
+            min_cards: None,
+            interests: Vec::new(),
+        }
+    }
+
+    pub fn address(mut self, address: impl Into<String>) -> Self {
+        self.address = address.into();
+        self
+    }
+
+    pub fn group(mut self, group: impl Into<String>) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+
+    pub fn min_cards(mut self, min_cards: MinCards) -> Self {
+        self.min_cards = Some(min_cards);
+        self
+    }
+
+    pub fn interests(mut self, interests: Vec<String>) -> Self {
+        self.interests = interests;
+        self
+    }
+
+    /// Sets how many cards they send. Since that usually differs from what they receive, this
+    /// also stops requiring them to be balanced; call [`Self::balanced`] afterwards to keep it.
+    pub fn sends(mut self, range: CardRange) -> Self {
+        self.sends = Some(range);
+        self.balanced = false;
+        self
+    }
+
+    /// Sets how many cards they receive. Like [`Self::sends`], this stops requiring them to be
+    /// balanced.
+    pub fn receives(mut self, range: CardRange) -> Self {
+        self.receives = Some(range);
+        self.balanced = false;
+        self
+    }
+
+    pub fn balanced(mut self, balanced: bool) -> Self {
+        self.balanced = balanced;
+        self
+    }
+
+    pub fn role(mut self, role: Role) -> Self {
+        self.role = role;
+        self
+    }
+
+    pub fn country(mut self, country: impl Into<String>) -> Self {
+        self.country = Some(country.into());
+        self
+    }
+
+    pub fn location(mut self, latitude: f64, longitude: f64) -> Self {
+        self.location = Some((latitude, longitude));
+        self
+    }
+
+    pub fn max_international(mut self, max: u32) -> Self {
+        self.max_international = Some(max);
+        self
+    }
+
+    pub fn international_mix(mut self, mix: InternationalMix) -> Self {
+        self.international_mix = mix;
+        self
+    }
+
+    /// How many cards they send.
+    pub fn send_range(&self) -> CardRange {
+        match self.role {
+            Role::ReceiveOnly => CardRange::up_to(0),
+            _ => self.sends.unwrap_or(CardRange::up_to(self.num_cards)),
+        }
+    }
+
+    /// How many cards they receive.
+    pub fn receive_range(&self) -> CardRange {
+        match self.role {
+            Role::SendOnly => CardRange::up_to(0),
+            _ => self.receives.unwrap_or(CardRange::up_to(self.num_cards)),
+        }
+    }
+
+    /// Whether they must receive exactly as many cards as they send.
+    pub fn is_balanced(&self) -> bool {
+        self.balanced && self.role == Role::Both
+    }
+}
+
+/// Whether a participant sends cards, receives them, or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Role {
+    #[default]
+    Both,
+    /// Volunteers who only send cards.
+    SendOnly,
+    /// People who only receive cards, such as nursing-home residents.
+    ReceiveOnly,
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    /// Parses `both`, `send-only` (or `sender`) and `receive-only` (or `recipient`).
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "both" => Ok(Role::Both),
+            "send-only" | "sender" => Ok(Role::SendOnly),
+            "receive-only" | "recipient" => Ok(Role::ReceiveOnly),
+            other => anyhow::bail!("Unknown role: {}", other),
+        }
+    }
+}
+
+/// How much covering receive-only participants (getting each of them at least one card) matters.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CoveragePriority {
+    /// Cover as many as possible before optimizing anything else.
+    #[default]
+    First,
+    /// Add this much to the total weight for each one covered.
+    Weighted(f64),
+}
+
+/// When to stop searching and settle for the best pairing found so far. With no limits, every
+/// solve runs until it proves its pairing optimal.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SolveLimits {
+    /// Seconds for each pairing, across all its objective levels.
+    pub time: Option<f64>,
+    /// Relative gap between the best pairing and the proven bound at which each level stops,
+    /// e.g. `0.01` for 1%.
+    pub gap: Option<f64>,
+    /// Branch-and-bound nodes for each objective level.
+    pub nodes: Option<u64>,
+}
+
+/// Lower and upper bounds on a number of cards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CardRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl CardRange {
+    pub fn new(min: u32, max: u32) -> Self {
+        assert!(min <= max, "Empty card range: {}-{}", min, max);
+        CardRange { min, max }
+    }
+
+    /// Anything from none to `max`.
+    pub fn up_to(max: u32) -> Self {
+        CardRange { min: 0, max }
+    }
+}
+
+impl fmt::Display for CardRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == 0 {
+            write!(f, "up to {}", self.max)
+        } else if self.min == self.max {
+            write!(f, "{}", self.max)
+        } else {
+            write!(f, "{}-{}", self.min, self.max)
+        }
+    }
+}
+
+impl FromStr for CardRange {
+    type Err = anyhow::Error;
+
+    /// Parses `"5"` as up to 5 cards and `"2-5"` as 2 to 5 cards.
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<u32>()
+                .map_err(|_| anyhow::anyhow!("Invalid card count: {}", s))
+        };
+        match s.split_once('-') {
+            Some((min, max)) => {
+                let (min, max) = (parse(min)?, parse(max)?);
+                if min > max {
+                    anyhow::bail!("Empty card range: {}", s);
+                }
+                Ok(CardRange::new(min, max))
+            }
+            None => Ok(CardRange::up_to(parse(s)?)),
+        }
+    }
+}
+
+/// A lower bound on the number of cards someone receives (and so sends).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MinCards {
+    /// At least this many cards, or every card requested if that is fewer.
+    Absolute(u32),
+    /// At least this fraction of the cards requested, rounded up.
+    Fraction(f64),
+}
+
+impl MinCards {
+    /// The minimum for someone who requested `num_cards` cards.
+    pub fn for_request(self, num_cards: u32) -> u32 {
+        match self {
+            MinCards::Absolute(min) => min.min(num_cards),
+            // Subtract a little so that e.g. 0.3 * 10 does not round up to 4.
+            MinCards::Fraction(fraction) => {
+                ((fraction * num_cards as f64 - 1e-9).ceil().max(0.) as u32).min(num_cards)
+            }
+        }
+    }
+}
+
+impl FromStr for MinCards {
+    type Err = anyhow::Error;
+
+    /// Parses `"2"` as an absolute minimum and `"50%"` as a fraction of the request.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        match s.strip_suffix('%') {
+            Some(percent) => {
+                let percent: f64 = percent
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid percentage: {}", s))?;
+                if !(0. ..=100.).contains(&percent) {
+                    anyhow::bail!("Percentage out of range: {}", s);
+                }
+                Ok(MinCards::Fraction(percent / 100.))
+            }
+            None => {
+                Ok(MinCards::Absolute(s.parse().map_err(|_| {
+                    anyhow::anyhow!("Invalid card count: {}", s)
+                })?))
+            }
+        }
+    }
+}
+
+/// The input to the pairings solver: who takes part, how many cards each of them wants, and
+/// per-pair data that shapes the objective.
+///
+/// Participants are referred to by their index in the order they were added; names are only
+/// used to look those indices up.
+#[derive(Debug, Clone, Default)]
+pub struct PairingProblem {
+    participants: Vec<Participant>,
+    /// Objective weight of person `i` sending a card to person `j`, indexed `[i][j]`.
+    pair_weights: Vec<Vec<f64>>,
+    /// Groups of participants who may not exchange cards with each other in either direction.
+    exclusion_groups: Vec<Vec<usize>>,
+    /// `(sender, receiver)` pairs that may not be used.
+    forbidden_pairs: Vec<(usize, usize)>,
+    /// `(sender, receiver)` pairs that must be used, e.g. because the organizer already promised
+    /// them.
+    required_pairs: Vec<(usize, usize)>,
+    /// `(sender, receiver, age)` pairings used in previous exchanges.
+    past_pairs: Vec<(usize, usize, u32)>,
+    history_mode: HistoryMode,
+    /// Minimum for participants that do not set their own.
+    min_cards: Option<MinCards>,
+    objective_mode: ObjectiveMode,
+    /// Seed for breaking ties between equally good pairings at random.
+    seed: Option<u64>,
+    /// `(sender, receiver)` names of the pairing being repaired, including people who have since
+    /// dropped out.
+    previous_solution: Vec<(String, String)>,
+    /// How much keeping or changing a pair of [`Self::previous_solution`] is worth, or `None` for
+    /// [`DEFAULT_CHANGE_PENALTY`].
+    change_penalty: Option<f64>,
+    /// `(sender, receiver)` pairs already handed out, which new participants are added around.
+    existing_pairings: Vec<(usize, usize)>,
+    /// How many of the [`Self::existing_pairings`] may change; with none, they are fixed.
+    allowed_changes: usize,
+    /// `(sender, receiver, score)` preferences given explicitly, overriding shared interests.
+    preferences: Vec<(usize, usize, f64)>,
+    /// How much a preference score of 1 adds to a pair's objective weight, or `None` for
+    /// [`DEFAULT_PREFERENCE_WEIGHT`].
+    preference_weight: Option<f64>,
+    /// The shortest exchange cycle allowed. Anything below 3 means 3: mutual exchanges are always
+    /// forbidden.
+    min_cycle_length: usize,
+    /// Whether everyone sends one card, around as few rings as possible.
+    single_ring: bool,
+    /// Whether everyone with cards to send must be able to reach everyone else who does.
+    strongly_connected: bool,
+    coverage_priority: CoveragePriority,
+    shipping_costs: ShippingCosts,
+    /// Whether to minimize shipping costs once everything else is optimal.
+    minimize_shipping: bool,
+    limits: SolveLimits,
+    progress: Option<ProgressCallback>,
+    /// Whether SCIP also tries rounding the LP relaxation into a pairing as it searches.
+    rounding_heuristic: bool,
+}
+
+/// The default [`PairingProblem::change_penalty`]: kept pairs weigh 1.5 and new ones 0.5.
+pub const DEFAULT_CHANGE_PENALTY: f64 = 0.5;
+
+impl PairingProblem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a problem with one anonymous participant per entry of `cards_for_participant`,
+    /// named `P1`, `P2`, ... in order.
+    pub fn from_card_counts(cards_for_participant: &[u32]) -> Self {
+        cards_for_participant
+            .iter()
+            .enumerate()
+            .fold(Self::new(), |problem, (i, &num_cards)| {
+                problem.participant(Participant::new(format!("P{}", i + 1), num_cards))
+            })
+    }
+
+    /// Creates a problem from a roster, keeping the roster's order.
+    pub fn from_participants(participants: Vec<Participant>) -> Self {
+        participants
+            .into_iter()
+            .fold(Self::new(), Self::participant)
+    }
+
+    /// Adds a participant. Every pair involving them starts with a weight of 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a participant with the same name was already added.
+    pub fn participant(mut self, participant: Participant) -> Self {
+        assert!(
+            self.index_of(&participant.name).is_none(),
+            "Duplicate participant name: {}",
+            participant.name
+        );
+        for row in &mut self.pair_weights {
+            row.push(1.0);
+        }
+        self.participants.push(participant);
+        self.pair_weights.push(vec![1.0; self.participants.len()]);
+        self
+    }
+
+    /// Sets the objective weight of `sender` sending a card to `receiver`.
+    pub fn pair_weight(mut self, sender: usize, receiver: usize, weight: f64) -> Self {
+        self.pair_weights[sender][receiver] = weight;
+        self
+    }
+
+    /// Sets how good a match `sender` sending to `receiver` is, instead of deriving it from shared
+    /// interests.
+    pub fn pair_preference(mut self, sender: usize, receiver: usize, score: f64) -> Self {
+        assert!(sender < self.participants.len() && receiver < self.participants.len());
+        self.preferences
+            .retain(|&(i, j, _)| (i, j) != (sender, receiver));
+        self.preferences.push((sender, receiver, score));
+        self
+    }
+
+    /// Sets preferences by name. Preferences involving someone who is not taking part are
+    /// ignored.
+    pub fn preferences(self, preferences: &[Preference]) -> Self {
+        preferences.iter().fold(self, |problem, preference| {
+            match (
+                problem.index_of(&preference.sender),
+                problem.index_of(&preference.receiver),
+            ) {
+                (Some(i), Some(j)) => problem.pair_preference(i, j, preference.score),
+                _ => problem,
+            }
+        })
+    }
+
+    /// Sets how much a preference score of 1 adds to a pair's objective weight.
+    pub fn preference_weight(mut self, weight: f64) -> Self {
+        self.preference_weight = Some(weight);
+        self
+    }
+
+    /// Forbids exchange cycles shorter than `length`, e.g. 4 to rule out A -> B -> C -> A as well
+    /// as mutual exchanges. Lengths below 3 change nothing, since mutual exchanges are always
+    /// forbidden.
+    pub fn forbid_short_cycles(mut self, min_length: usize) -> Self {
+        self.min_cycle_length = min_length;
+        self
+    }
+
+    /// Classic Secret Santa: everyone with cards to send sends and receives exactly one, in a
+    /// single ring through all of them, or in as few rings as the other rules allow.
+    pub fn single_ring(mut self) -> Self {
+        self.single_ring = true;
+        self
+    }
+
+    /// Requires everyone who both sends and receives cards to be able to reach everyone else who
+    /// does by following the chain of sent cards, so the exchange does not split into isolated
+    /// cliques. Send-only and receive-only participants cannot be on a cycle, so they are left out.
+    pub fn require_strong_connectivity(mut self) -> Self {
+        self.strongly_connected = true;
+        self
+    }
+
+    /// Sets how much getting a card to every receive-only participant matters.
+    pub fn coverage_priority(mut self, priority: CoveragePriority) -> Self {
+        self.coverage_priority = priority;
+        self
+    }
+
+    /// Sets how shipping costs are worked out.
+    pub fn shipping_costs(mut self, costs: ShippingCosts) -> Self {
+        self.shipping_costs = costs;
+        self
+    }
+
+    /// Minimizes the total shipping cost as a secondary objective, among pairings that are
+    /// optimal for the [`ObjectiveMode`].
+    pub fn minimize_shipping(mut self) -> Self {
+        self.minimize_shipping = true;
+        self
+    }
+
+    /// Stops solving after `seconds`, keeping the best pairing found by then.
+    pub fn time_limit(mut self, seconds: f64) -> Self {
+        self.limits.time = Some(seconds);
+        self
+    }
+
+    /// Stops solving once the best pairing is provably within `gap` (relative, e.g. `0.01`) of
+    /// the optimum.
+    pub fn gap_limit(mut self, gap: f64) -> Self {
+        self.limits.gap = Some(gap);
+        self
+    }
+
+    /// Stops solving each objective level after `nodes` branch-and-bound nodes.
+    pub fn node_limit(mut self, nodes: u64) -> Self {
+        self.limits.nodes = Some(nodes);
+        self
+    }
+
+    /// Has SCIP round the LP relaxation into a pairing every few nodes, on top of its own
+    /// heuristics. The `benchmark` binary compares solve times with and without it.
+    pub fn rounding_heuristic(mut self) -> Self {
+        self.rounding_heuristic = true;
+        self
+    }
+
+    /// Calls `callback` whenever the solver finds a better pairing.
+    pub fn on_progress(mut self, callback: impl Fn(&Progress) + 'static) -> Self {
+        self.progress = Some(ProgressCallback::new(callback));
+        self
+    }
+
+    /// Forbids every exchange, in either direction, between the given participants.
+    pub fn exclusion_group(mut self, members: Vec<usize>) -> Self {
+        assert!(members.iter().all(|&i| i < self.participants.len()));
+        self.exclusion_groups.push(members);
+        self
+    }
+
+    /// Forbids `sender` from sending a card to `receiver`.
+    pub fn forbid_pair(mut self, sender: usize, receiver: usize) -> Self {
+        assert!(sender < self.participants.len() && receiver < self.participants.len());
+        self.forbidden_pairs.push((sender, receiver));
+        self
+    }
+
+    /// Requires `sender` to send a card to `receiver`.
+    pub fn require_pair(mut self, sender: usize, receiver: usize) -> Self {
+        assert!(sender < self.participants.len() && receiver < self.participants.len());
+        if !self.required_pairs.contains(&(sender, receiver)) {
+            self.required_pairs.push((sender, receiver));
+        }
+        self
+    }
+
+    /// Records that `sender` sent to `receiver` in the exchange `age` exchanges ago: 1 for the most
+    /// recent one. An age of 0 counts as 1.
+    pub fn past_pairing(mut self, sender: usize, receiver: usize, age: u32) -> Self {
+        assert!(sender < self.participants.len() && receiver < self.participants.len());
+        self.past_pairs.push((sender, receiver, age));
+        self
+    }
+
+    /// Records previous exchanges by name. Pairings involving someone who is not taking part this
+    /// time are ignored.
+    pub fn history(self, history: &[PastPairing]) -> Self {
+        history.iter().fold(self, |problem, past| {
+            match (
+                problem.index_of(&past.sender),
+                problem.index_of(&past.receiver),
+            ) {
+                (Some(i), Some(j)) => problem.past_pairing(i, j, past.age),
+                _ => problem,
+            }
+        })
+    }
+
+    /// Sets how pairings recorded with [`Self::history`] are treated.
+    pub fn handle_repeats(mut self, mode: HistoryMode) -> Self {
+        self.history_mode = mode;
+        self
+    }
+
+    /// Sets the minimum number of cards for every participant without their own minimum.
+    pub fn min_cards(mut self, min_cards: MinCards) -> Self {
+        self.min_cards = Some(min_cards);
+        self
+    }
+
+    /// Sets what the solver optimizes.
+    pub fn optimize(mut self, mode: ObjectiveMode) -> Self {
+        self.objective_mode = mode;
+        self
+    }
+
+    /// Breaks ties between equally good pairings pseudo-randomly, so that different seeds give
+    /// different (but equally optimal) pairings and the same seed always gives the same one.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Repairs an earlier pairing, given as `(sender, receiver)` names, after the roster changed:
+    /// pairs from it are kept where possible, and changed as little as possible otherwise.
+    /// Pairs involving someone who is no longer taking part are dropped.
+    pub fn previous_solution(mut self, pairings: &[(String, String)]) -> Self {
+        self.previous_solution = pairings.to_vec();
+        self
+    }
+
+    /// Sets how much each pair of the [`Self::previous_solution`] that is kept adds to the
+    /// objective, and each new pair subtracts.
+    pub fn change_penalty(mut self, penalty: f64) -> Self {
+        self.change_penalty = Some(penalty);
+        self
+    }
+
+    /// Adds participants to a pairing that was already handed out: its pairs stay as they are,
+    /// and anyone who is in none of them is fitted in around them.
+    pub fn append_to(mut self, pairings: &[(usize, usize)]) -> Self {
+        for &(sender, receiver) in pairings {
+            assert!(sender < self.participants.len() && receiver < self.participants.len());
+        }
+        self.existing_pairings = pairings.to_vec();
+        self
+    }
+
+    /// Lets up to `max_changes` pairs between existing participants be dropped or added when
+    /// appending, so new participants can be fitted in more easily.
+    pub fn allow_changes(mut self, max_changes: usize) -> Self {
+        self.allowed_changes = max_changes;
+        self
+    }
+
+    pub fn participants(&self) -> &[Participant] {
+        &self.participants
+    }
+
+    /// Looks up a participant's index by name.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.participants.iter().position(|p| p.name == name)
+    }
+
+    pub fn num_participants(&self) -> usize {
+        self.participants.len()
+    }
+
+    pub fn weight(&self, sender: usize, receiver: usize) -> f64 {
+        self.pair_weights[sender][receiver]
+    }
+
+    /// The fewest cards `participant` must receive (and send).
+    pub fn min_cards_for(&self, participant: usize) -> u32 {
+        let participant = &self.participants[participant];
+        participant
+            .min_cards
+            .or(self.min_cards)
+            .map_or(0, |min| min.for_request(participant.receive_range().max))
+    }
+
+    pub fn objective_mode(&self) -> &ObjectiveMode {
+        &self.objective_mode
+    }
+
+    pub fn random_seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// The shortest exchange cycle allowed.
+    pub fn min_cycle_length(&self) -> usize {
+        self.min_cycle_length.max(3)
+    }
+
+    pub fn is_single_ring(&self) -> bool {
+        self.single_ring
+    }
+
+    pub fn requires_strong_connectivity(&self) -> bool {
+        self.strongly_connected
+    }
+
+    pub fn coverage(&self) -> CoveragePriority {
+        self.coverage_priority
+    }
+
+    /// Participants who only receive cards.
+    pub fn receive_only(&self) -> Vec<usize> {
+        (0..self.participants.len())
+            .filter(|&i| self.participants[i].role == Role::ReceiveOnly)
+            .collect()
+    }
+
+    /// What `sender` sending a card to `receiver` costs to ship.
+    pub fn shipping_cost(&self, sender: usize, receiver: usize) -> f64 {
+        self.shipping_costs
+            .cost(&self.participants[sender], &self.participants[receiver])
+    }
+
+    pub fn minimizes_shipping(&self) -> bool {
+        self.minimize_shipping
+    }
+
+    pub fn limits(&self) -> SolveLimits {
+        self.limits
+    }
+
+    pub fn uses_rounding_heuristic(&self) -> bool {
+        self.rounding_heuristic
+    }
+
+    pub fn progress_callback(&self) -> Option<&ProgressCallback> {
+        self.progress.as_ref()
+    }
+
+    /// Whether `sender` sending to `receiver` crosses a border.
+    pub fn is_international(&self, sender: usize, receiver: usize) -> bool {
+        is_international(&self.participants[sender], &self.participants[receiver])
+    }
+
+    /// The total shipping cost of `solution`.
+    pub fn total_shipping_cost(&self, solution: &PairingSolution) -> f64 {
+        solution
+            .pairings
+            .iter()
+            .map(|&(i, j)| self.shipping_cost(i, j))
+            .sum()
+    }
+
+    pub fn exclusion_groups(&self) -> &[Vec<usize>] {
+        &self.exclusion_groups
+    }
+
+    pub fn forbidden_pairs(&self) -> &[(usize, usize)] {
+        &self.forbidden_pairs
+    }
+
+    /// Whether `a` and `b` are different people in the same [`Participant::group`].
+    pub fn same_group(&self, a: usize, b: usize) -> bool {
+        let group = &self.participants[a].group;
+        a != b && group.is_some() && *group == self.participants[b].group
+    }
+
+    /// Every `(sender, receiver)` pair ruled out by an exclusion group or a shared
+    /// [`Participant::group`], without duplicates.
+    pub fn group_excluded_pairs(&self) -> Vec<(usize, usize)> {
+        let n = self.participants.len();
+        let mut pairs: Vec<(usize, usize)> = (0..n)
+            .flat_map(|i| (0..n).map(move |j| (i, j)))
+            .filter(|&(i, j)| self.same_group(i, j))
+            .collect();
+        for members in &self.exclusion_groups {
+            for &i in members {
+                for &j in members {
+                    if i != j && !pairs.contains(&(i, j)) {
+                        pairs.push((i, j));
+                    }
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Whether `sender` may not send to `receiver` because of an exclusion group, a shared
+    /// [`Participant::group`] or an explicitly forbidden pair.
+    pub fn is_forbidden(&self, sender: usize, receiver: usize) -> bool {
+        self.forbidden_pairs.contains(&(sender, receiver))
+            || self.same_group(sender, receiver)
+            || self.exclusion_groups.iter().any(|members| {
+                sender != receiver && members.contains(&sender) && members.contains(&receiver)
+            })
+    }
+
+    pub fn required_pairs(&self) -> &[(usize, usize)] {
+        &self.required_pairs
+    }
+
+    pub fn past_pairs(&self) -> &[(usize, usize, u32)] {
+        &self.past_pairs
+    }
+
+    pub fn history_mode(&self) -> HistoryMode {
+        self.history_mode
+    }
+
+    /// Whether `sender` sent to `receiver` in a previous exchange.
+    pub fn is_repeat(&self, sender: usize, receiver: usize) -> bool {
+        self.past_pairs
+            .iter()
+            .any(|&(i, j, _)| i == sender && j == receiver)
+    }
+
+    /// How much using the pair again is penalized in the objective.
+    pub fn repeat_penalty(&self, sender: usize, receiver: usize) -> f64 {
+        match self.history_mode {
+            HistoryMode::Forbid => 0.,
+            HistoryMode::Penalize { penalty, decay } => self
+                .past_pairs
+                .iter()
+                .filter(|&&(i, j, _)| i == sender && j == receiver)
+                .map(|&(_, _, age)| penalty * decay.powi(age.max(1) as i32 - 1))
+                .sum(),
+        }
+    }
+
+    pub fn existing_pairings(&self) -> &[(usize, usize)] {
+        &self.existing_pairings
+    }
+
+    /// Whether `participant` joins a pairing that was already handed out. Without one, nobody is
+    /// new.
+    pub fn is_new(&self, participant: usize) -> bool {
+        !self.existing_pairings.is_empty()
+            && !self
+                .existing_pairings
+                .iter()
+                .any(|&(i, j)| i == participant || j == participant)
+    }
+
+    /// How many pairs between existing participants may change, if some may.
+    pub fn max_changes(&self) -> Option<usize> {
+        (!self.existing_pairings.is_empty() && self.allowed_changes > 0)
+            .then_some(self.allowed_changes)
+    }
+
+    /// Whether `sender` sending to `receiver` is decided in advance, because both were in a
+    /// pairing that may not change.
+    pub fn fixed_pair(&self, sender: usize, receiver: usize) -> Option<bool> {
+        let fixed = !self.existing_pairings.is_empty()
+            && self.allowed_changes == 0
+            && !self.is_new(sender)
+            && !self.is_new(receiver);
+        fixed.then(|| self.existing_pairings.contains(&(sender, receiver)))
+    }
+
+    /// Pairs between existing participants that `solution` drops from, or adds to, the pairing it
+    /// was appended to.
+    pub fn changed_existing_pairs(&self, solution: &PairingSolution) -> Vec<(usize, usize)> {
+        if self.existing_pairings.is_empty() {
+            return Vec::new();
+        }
+        let dropped = self
+            .existing_pairings
+            .iter()
+            .copied()
+            .filter(|&(i, j)| !solution.has_pairing(i, j));
+        let added = solution.pairings.iter().copied().filter(|&(i, j)| {
+            !self.is_new(i) && !self.is_new(j) && !self.existing_pairings.contains(&(i, j))
+        });
+        dropped.chain(added).collect()
+    }
+
+    /// The pairing being repaired, as `(sender, receiver)` names.
+    pub fn previous_pairings(&self) -> &[(String, String)] {
+        &self.previous_solution
+    }
+
+    /// Whether `sender` sends to `receiver` in the pairing being repaired.
+    pub fn was_paired(&self, sender: usize, receiver: usize) -> bool {
+        let sender = &self.participants[sender].name;
+        let receiver = &self.participants[receiver].name;
+        self.previous_solution
+            .iter()
+            .any(|(s, r)| s == sender && r == receiver)
+    }
+
+    /// What keeping the pair from the pairing being repaired is worth: the change penalty if it
+    /// was used there, minus the penalty if it is new, and nothing when not repairing.
+    pub fn stability_bonus(&self, sender: usize, receiver: usize) -> f64 {
+        if self.previous_solution.is_empty() {
+            return 0.;
+        }
+        let penalty = self.change_penalty.unwrap_or(DEFAULT_CHANGE_PENALTY);
+        if self.was_paired(sender, receiver) {
+            penalty
+        } else {
+            -penalty
+        }
+    }
+
+    /// How good a match `sender` sending to `receiver` is: the score given with
+    /// [`Self::pair_preference`], or else how many of the receiver's interests the sender shares.
+    pub fn preference(&self, sender: usize, receiver: usize) -> f64 {
+        self.preferences
+            .iter()
+            .find(|&&(i, j, _)| (i, j) == (sender, receiver))
+            .map_or_else(
+                || {
+                    shared_interest_score(
+                        &self.participants[sender].interests,
+                        &self.participants[receiver].interests,
+                    )
+                },
+                |&(_, _, score)| score,
+            )
+    }
+
+    /// Whether any preferences were given, explicitly or through interests.
+    pub fn has_preferences(&self) -> bool {
+        !self.preferences.is_empty() || self.participants.iter().any(|p| !p.interests.is_empty())
+    }
+
+    /// The objective coefficient of `sender` sending to `receiver`: its weight plus its weighted
+    /// preference, less any repeat penalty, adjusted to keep the pairing being repaired.
+    pub fn objective_weight(&self, sender: usize, receiver: usize) -> f64 {
+        let preference_weight = self.preference_weight.unwrap_or(DEFAULT_PREFERENCE_WEIGHT);
+        self.weight(sender, receiver) + preference_weight * self.preference(sender, receiver)
+            - self.repeat_penalty(sender, receiver)
+            + self.stability_bonus(sender, receiver)
+    }
+
+    /// For each participant, the mean preference score of the cards they receive in `solution`,
+    /// or `None` if they receive none.
+    pub fn preference_satisfaction(&self, solution: &PairingSolution) -> Vec<Option<f64>> {
+        (0..self.participants.len())
+            .map(|j| {
+                let senders = solution.received_by(j);
+                (!senders.is_empty()).then(|| {
+                    senders.iter().map(|&i| self.preference(i, j)).sum::<f64>()
+                        / senders.len() as f64
+                })
+            })
+            .collect()
+    }
+
+    /// Everyone whose partners in `solution` differ from those in the pairing being repaired.
+    pub fn partner_changes(&self, solution: &PairingSolution) -> Vec<PartnerChange> {
+        if self.previous_solution.is_empty() {
+            return Vec::new();
+        }
+        let names = |pairs: Vec<usize>| -> Vec<&str> {
+            pairs
+                .into_iter()
+                .map(|j| self.participants[j].name.as_str())
+                .collect()
+        };
+        let missing = |from: &[&str], to: &[&str]| -> Vec<String> {
+            from.iter()
+                .filter(|name| !to.contains(name))
+                .map(|name| name.to_string())
+                .collect()
+        };
+
+        let mut changes = Vec::new();
+        for (i, participant) in self.participants.iter().enumerate() {
+            let name = participant.name.as_str();
+            let sends_to = names(solution.sent_by(i));
+            let receives_from = names(solution.received_by(i));
+            let sent_to: Vec<&str> = self
+                .previous_solution
+                .iter()
+                .filter(|(s, _)| s == name)
+                .map(|(_, r)| r.as_str())
+                .collect();
+            let received_from: Vec<&str> = self
+                .previous_solution
+                .iter()
+                .filter(|(_, r)| r == name)
+                .map(|(s, _)| s.as_str())
+                .collect();
+
+            let change = PartnerChange {
+                participant: participant.name.clone(),
+                new_recipients: missing(&sends_to, &sent_to),
+                lost_recipients: missing(&sent_to, &sends_to),
+                new_senders: missing(&receives_from, &received_from),
+                lost_senders: missing(&received_from, &receives_from),
+            };
+            if !change.is_empty() {
+                changes.push(change);
+            }
+        }
+        changes
+    }
+
+    /// Pairings in `solution` that were already used in a previous exchange.
+    pub fn repeated_pairs(&self, solution: &PairingSolution) -> Vec<(usize, usize)> {
+        solution
+            .pairings
+            .iter()
+            .copied()
+            .filter(|&(i, j)| self.is_repeat(i, j))
+            .collect()
+    }
+
+    /// What forbidding past pairings costs `solution`, found by solving again with repeats only
+    /// penalized. `None` unless repeats are forbidden and there are past pairings.
+    pub fn repeat_ban_cost(&self, solution: &PairingSolution) -> Result<Option<RepeatBanCost>> {
+        if self.history_mode != HistoryMode::Forbid || self.past_pairs.is_empty() {
+            return Ok(None);
+        }
+        let mut penalized = self.clone().handle_repeats(HistoryMode::Penalize {
+            penalty: DEFAULT_REPEAT_PENALTY,
+            decay: DEFAULT_REPEAT_DECAY,
+        });
+        penalized.progress = None;
+        let best = penalized.solve()?;
+        Ok(Some(RepeatBanCost {
+            cards_lost: best.pairings.len().saturating_sub(solution.pairings.len()),
+            repeats_needed: self.repeated_pairs(&best).len(),
+        }))
+    }
+
+    /// Required pairs that can never be part of a valid pairing, because they break one of the
+    /// other rules on their own.
+    pub fn required_pair_conflicts(&self) -> Vec<RequiredPairConflict> {
+        let name = |i: usize| self.participants[i].name.clone();
+        let mut conflicts = Vec::new();
+
+        for &(i, j) in &self.required_pairs {
+            if i == j {
+                conflicts.push(RequiredPairConflict::SelfExchange(name(i)));
+            } else if self.is_forbidden(i, j) {
+                conflicts.push(RequiredPairConflict::Forbidden(name(i), name(j)));
+            } else if self.history_mode == HistoryMode::Forbid && self.is_repeat(i, j) {
+                conflicts.push(RequiredPairConflict::Repeat(name(i), name(j)));
+            } else if i < j && self.required_pairs.contains(&(j, i)) {
+                conflicts.push(RequiredPairConflict::MutualExchange(name(i), name(j)));
+            }
+        }
+
+        for (i, participant) in self.participants.iter().enumerate() {
+            let sends = self.required_pairs.iter().filter(|(s, _)| *s == i).count();
+            let receives = self.required_pairs.iter().filter(|(_, r)| *r == i).count();
+            let max_sent = participant.send_range().max;
+            if sends > max_sent as usize {
+                conflicts.push(RequiredPairConflict::SendsOverCapacity {
+                    participant: name(i),
+                    required: sends,
+                    num_cards: max_sent,
+                });
+            }
+            let max_received = participant.receive_range().max;
+            if receives > max_received as usize {
+                conflicts.push(RequiredPairConflict::ReceivesOverCapacity {
+                    participant: name(i),
+                    required: receives,
+                    num_cards: max_received,
+                });
+            }
+        }
+
+        conflicts
+    }
+
+    /// Pairings in `solution` that break an exclusion group or a forbidden pair.
+    pub fn violated_exclusions(&self, solution: &PairingSolution) -> Vec<(usize, usize)> {
+        solution
+            .pairings
+            .iter()
+            .copied()
+            .filter(|&(i, j)| self.is_forbidden(i, j))
+            .collect()
+    }
+
+    /// Required pairs that `solution` does not use.
+    pub fn missing_required_pairs(&self, solution: &PairingSolution) -> Vec<(usize, usize)> {
+        self.required_pairs
+            .iter()
+            .copied()
+            .filter(|&(i, j)| !solution.has_pairing(i, j))
+            .collect()
+    }
+
+    /// The same problem with nothing required, to tell whether required pairs are what rules out
+    /// every pairing.
+    pub(crate) fn without_required_pairs(&self) -> Self {
+        Self {
+            required_pairs: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    pub fn solve(&self) -> Result<PairingSolution> {
+        generate_pairings(self)
+    }
+
+    /// Solves for up to `count` pairings that each differ from all earlier ones in at least
+    /// `min_difference` pairs, best first.
+    pub fn solve_distinct(
+        &self,
+        count: usize,
+        min_difference: usize,
+    ) -> Result<Vec<PairingSolution>> {
+        generate_distinct_pairings(self, count, min_difference)
+    }
+}
+
+/// How someone's partners changed when a pairing was repaired. Participants are identified by
+/// name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartnerChange {
+    pub participant: String,
+    /// People they now send to, but did not before.
+    pub new_recipients: Vec<String>,
+    /// People they sent to before, but no longer do.
+    pub lost_recipients: Vec<String>,
+    /// People who now send to them, but did not before.
+    pub new_senders: Vec<String>,
+    /// People who sent to them before, but no longer do.
+    pub lost_senders: Vec<String>,
+}
+
+impl PartnerChange {
+    pub fn is_empty(&self) -> bool {
+        self.new_recipients.is_empty()
+            && self.lost_recipients.is_empty()
+            && self.new_senders.is_empty()
+            && self.lost_senders.is_empty()
+    }
+}
+
+/// A reason a required pair can never be satisfied. Participants are identified by name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequiredPairConflict {
+    /// Someone is required to send a card to themself.
+    SelfExchange(String),
+    /// A required pair is also forbidden, by an exclusion group or explicitly.
+    Forbidden(String, String),
+    /// A required pair was used before, and repeats are forbidden.
+    Repeat(String, String),
+    /// Both directions between two people are required, but mutual exchanges are not allowed.
+    MutualExchange(String, String),
+    /// Someone is required to send more cards than they signed up for.
+    SendsOverCapacity {
+        participant: String,
+        required: usize,
+        num_cards: u32,
+    },
+    /// Someone is required to receive more cards than they signed up for.
+    ReceivesOverCapacity {
+        participant: String,
+        required: usize,
+        num_cards: u32,
+    },
+}
+
+impl fmt::Display for RequiredPairConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequiredPairConflict::SelfExchange(name) => {
+                write!(f, "{} is required to send to themself", name)
+            }
+            RequiredPairConflict::Forbidden(sender, receiver) => write!(
+                f,
+                "{} -> {} is required but also forbidden",
+                sender, receiver
+            ),
+            RequiredPairConflict::Repeat(sender, receiver) => write!(
+                f,
+                "{} -> {} is required but was used in a previous exchange",
+                sender, receiver
+            ),
+            RequiredPairConflict::MutualExchange(a, b) => write!(
+                f,
+                "{} -> {} and {} -> {} are both required, but mutual exchanges are not allowed",
+                a, b, b, a
+            ),
+            RequiredPairConflict::SendsOverCapacity {
+                participant,
+                required,
+                num_cards,
+            } => write!(
+                f,
+                "{} is required to send {} cards but signed up for {}",
+                participant, required, num_cards
+            ),
+            RequiredPairConflict::ReceivesOverCapacity {
+                participant,
+                required,
+                num_cards,
+            } => write!(
+                f,
+                "{} is required to receive {} cards but signed up for {}",
+                participant, required, num_cards
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_ranges_parse_counts_and_ranges() {
+        assert_eq!("5".parse::<CardRange>().unwrap(), CardRange::up_to(5));
+        assert_eq!(
+            " 2 - 5 ".parse::<CardRange>().unwrap(),
+            CardRange::new(2, 5)
+        );
+        assert!("5-2".parse::<CardRange>().is_err());
+        assert!("many".parse::<CardRange>().is_err());
+        assert!("-3".parse::<CardRange>().is_err());
+    }
+
+    #[test]
+    fn card_ranges_display_bounds_that_matter() {
+        for (range, shown) in [("5", "up to 5"), ("3-3", "3"), ("2-5", "2-5")] {
+            assert_eq!(range.parse::<CardRange>().unwrap().to_string(), shown);
+        }
+    }
+
+    #[test]
+    fn min_cards_parse_counts_and_percentages() {
+        assert_eq!("2".parse::<MinCards>().unwrap(), MinCards::Absolute(2));
+        assert_eq!("50%".parse::<MinCards>().unwrap(), MinCards::Fraction(0.5));
+        assert!("150%".parse::<MinCards>().is_err());
+        assert!("-10%".parse::<MinCards>().is_err());
+        assert!("some".parse::<MinCards>().is_err());
+    }
+
+    #[test]
+    fn min_cards_never_exceed_the_request() {
+        assert_eq!(MinCards::Absolute(5).for_request(3), 3);
+        assert_eq!(MinCards::Fraction(0.3).for_request(10), 3);
+        assert_eq!(MinCards::Fraction(0.5).for_request(3), 2);
+        assert_eq!(MinCards::Fraction(1.).for_request(4), 4);
+    }
+
+    #[test]
+    fn repeat_penalties_decay_with_age() {
+        let problem = PairingProblem::from_card_counts(&[1; 3])
+            .past_pairing(0, 1, 0)
+            .past_pairing(1, 2, 1)
+            .past_pairing(2, 0, 3)
+            .handle_repeats(HistoryMode::Penalize {
+                penalty: 0.5,
+                decay: 0.5,
+            });
+        assert_eq!(problem.repeat_penalty(0, 1), 0.5);
+        assert_eq!(problem.repeat_penalty(1, 2), 0.5);
+        assert_eq!(problem.repeat_penalty(2, 0), 0.125);
+        assert_eq!(problem.repeat_penalty(1, 0), 0.);
+    }
+
+    #[test]
+    fn roles_parse_with_aliases() {
+        assert_eq!("".parse::<Role>().unwrap(), Role::Both);
+        assert_eq!("Send-Only".parse::<Role>().unwrap(), Role::SendOnly);
+        assert_eq!("recipient".parse::<Role>().unwrap(), Role::ReceiveOnly);
+        assert!("observer".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn shared_groups_exclude_each_other() {
+        let problem = PairingProblem::from_participants(vec![
+            Participant::new("A", 1).group("home"),
+            Participant::new("B", 1).group("home"),
+            Participant::new("C", 1),
+            Participant::new("D", 1),
+        ]);
+        assert!(problem.is_forbidden(0, 1) && problem.is_forbidden(1, 0));
+        assert!(!problem.is_forbidden(0, 2));
+        // People without a group do not share one.
+        assert!(!problem.is_forbidden(2, 3));
+        assert_eq!(problem.group_excluded_pairs(), vec![(0, 1), (1, 0)]);
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+use std::rc::Rc;
+
+use russcip::{
+    Event, EventMask, Eventhdlr, Model, SCIPEventhdlr, Solving, WithSolutions, WithSolvingStats,
+    ffi,
+};
+
+use crate::Objective;
+use crate::solver::Pair;
+
+/// How the search is going, reported whenever SCIP finds a better pairing.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    /// The objective level being optimized, counting from 0 in priority order.
+    pub level: usize,
+    pub objective: Objective,
+    /// The objective value of the new best pairing.
+    pub value: f64,
+    /// The best objective value SCIP has proved possible so far.
+    pub dual_bound: f64,
+    /// The relative gap between [`Self::value`] and [`Self::dual_bound`].
+    pub gap: f64,
+    /// Seconds spent solving, across all levels so far.
+    pub elapsed: f64,
+    /// `(sender, receiver)` pairs of the new best pairing, as participant indices.
+    pub pairings: Vec<(usize, usize)>,
+}
+
+/// A function called with each [`Progress`] update while solving, e.g. to draw a progress bar or
+/// save intermediate pairings.
+#[derive(Clone)]
+pub struct ProgressCallback(Rc<dyn Fn(&Progress)>);
+
+impl ProgressCallback {
+    pub fn new(callback: impl Fn(&Progress) + 'static) -> Self {
+        ProgressCallback(Rc::new(callback))
+    }
+
+    pub fn call(&self, progress: &Progress) {
+        (self.0)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// The relative gap between the best solution and the dual bound of a model being solved.
+pub(crate) fn gap<S>(model: &Model<S>) -> f64 {
+    // SAFETY: the pointer is valid for as long as the model is alive.
+    unsafe { ffi::SCIPgetGap(model.scip_ptr()) }
+}
+
+/// Passes each new best solution of one objective level on to a [`ProgressCallback`].
+pub(crate) struct ProgressEventhdlr {
+    pub(crate) x: Vec<Vec<Pair>>,
+    pub(crate) level: usize,
+    pub(crate) objective: Objective,
+    /// What pairs fixed in advance add to the objective, since SCIP does not see them.
+    pub(crate) fixed_value: f64,
+    /// Seconds spent on earlier levels.
+    pub(crate) elapsed: f64,
+    pub(crate) callback: ProgressCallback,
+}
+
+impl Eventhdlr for ProgressEventhdlr {
+    fn get_type(&self) -> EventMask {
+        EventMask::BEST_SOL_FOUND
+    }
+
+    fn execute(&mut self, model: Model<Solving>, _eventhdlr: SCIPEventhdlr, _event: Event) {
+        let Some(sol) = model.best_sol() else {
+            return;
+        };
+        let mut pairings = Vec::new();
+        for (i, row) in self.x.iter().enumerate() {
+            for (j, pair) in row.iter().enumerate() {
+                if pair.value(&sol) > 0.5 {
+                    pairings.push((i, j));
+                }
+            }
+        }
+        self.callback.call(&Progress {
+            level: self.level,
+            objective: self.objective,
+            value: sol.obj_val() + self.fixed_value,
+            dual_bound: model.best_bound() + self.fixed_value,
+            gap: gap(&model),
+            elapsed: self.elapsed + model.solving_time(),
+            pairings,
+        });
+    }
+}
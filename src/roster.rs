@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{CardRange, MinCards, Participant, Role};
+
+/// One row of a roster file.
+///
+/// CSV rosters have a header row with these column names; JSON rosters are an array of objects
+/// with these keys.
+#[derive(Debug, Deserialize)]
+struct RosterEntry {
+    name: String,
+    #[serde(default, alias = "email")]
+    address: String,
+    #[serde(alias = "num_cards")]
+    cards: u32,
+    #[serde(default, alias = "household")]
+    group: Option<String>,
+    /// A [`MinCards`] such as `2` or `50%`.
+    #[serde(default, alias = "minimum")]
+    min_cards: Option<String>,
+    /// A [`CardRange`] such as `5` or `2-5`, if different from `cards`.
+    #[serde(default)]
+    send: Option<String>,
+    /// A [`CardRange`] for the cards received, if different from `cards`.
+    #[serde(default)]
+    receive: Option<String>,
+    /// Whether they must receive as many cards as they send. Defaults to yes, unless `send` or
+    /// `receive` is given.
+    #[serde(default)]
+    balanced: Option<bool>,
+    /// A [`Role`]: `both` (the default), `send-only` or `receive-only`.
+    #[serde(default)]
+    role: Option<String>,
+    #[serde(default, alias = "region")]
+    country: Option<String>,
+    #[serde(default, alias = "lat")]
+    latitude: Option<f64>,
+    #[serde(default, alias = "lon")]
+    longitude: Option<f64>,
+    /// The most cards they send abroad.
+    #[serde(default)]
+    max_international: Option<u32>,
+    /// `any`, `domestic` or the fewest cards from abroad.
+    #[serde(default, alias = "mix")]
+    international: Option<String>,
+    /// Interests separated by `;`, e.g. `hiking;french`.
+    #[serde(default, alias = "tags")]
+    interests: Option<String>,
+}
+
+/// Loads participants from a `.csv` or `.json` roster file, in file order.
+///
+/// Every entry needs a `name` and a `cards` count; `address` (or `email`), `group` (or
+/// `household`), `min_cards` (or `minimum`, e.g. `2` or `50%`), `send` and `receive` (e.g. `5`
+/// or `2-5`), `balanced`, `role` (`both`, `send-only` or `receive-only`), `country` (or
+/// `region`), `latitude` and `longitude`, `max_international`, `international` (or `mix`: `any`,
+/// `domestic` or the fewest cards from abroad) and `interests` (or `tags`, separated by `;`) are
+/// optional. Names must be unique, since they are how participants are identified in every other
+/// file.
+pub fn load_roster(path: impl AsRef<Path>) -> Result<Vec<Participant>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    let entries: Vec<RosterEntry> = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(file)
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("Failed to parse {}", path.display()))?,
+        Some("json") => serde_json::from_reader(file)
+            .with_context(|| format!("Failed to parse {}", path.display()))?,
+        _ => anyhow::bail!(
+            "Unknown roster format for {} (expected .csv or .json)",
+            path.display()
+        ),
+    };
+
+    let mut names = HashSet::new();
+    for entry in &entries {
+        if entry.name.trim().is_empty() {
+            anyhow::bail!("Roster {} has an entry without a name", path.display());
+        }
+        if !names.insert(entry.name.as_str()) {
+            anyhow::bail!("Roster {} lists {} twice", path.display(), entry.name);
+        }
+    }
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            let mut participant = Participant::new(entry.name, entry.cards).address(entry.address);
+            if let Some(group) = entry.group.filter(|group| !group.trim().is_empty()) {
+                participant = participant.group(group);
+            }
+            if let Some(min_cards) = entry.min_cards.filter(|min| !min.trim().is_empty()) {
+                let min_cards: MinCards = min_cards.parse().with_context(|| {
+                    format!(
+                        "Invalid min_cards for {} in {}",
+                        participant.name,
+                        path.display()
+                    )
+                })?;
+                participant = participant.min_cards(min_cards);
+            }
+            let name = participant.name.clone();
+            let range = |value: String, column: &str| -> Result<CardRange> {
+                value.parse().with_context(|| {
+                    format!("Invalid {} for {} in {}", column, name, path.display())
+                })
+            };
+            if let Some(send) = entry.send.filter(|send| !send.trim().is_empty()) {
+                participant = participant.sends(range(send, "send")?);
+            }
+            if let Some(receive) = entry.receive.filter(|receive| !receive.trim().is_empty()) {
+                participant = participant.receives(range(receive, "receive")?);
+            }
+            if let Some(balanced) = entry.balanced {
+                participant = participant.balanced(balanced);
+            }
+            if let Some(role) = entry.role {
+                let role: Role = role
+                    .parse()
+                    .with_context(|| format!("Invalid role for {} in {}", name, path.display()))?;
+                participant = participant.role(role);
+            }
+            if let Some(country) = entry.country.filter(|country| !country.trim().is_empty()) {
+                participant = participant.country(country);
+            }
+            match (entry.latitude, entry.longitude) {
+                (Some(latitude), Some(longitude)) => {
+                    participant = participant.location(latitude, longitude)
+                }
+                (None, None) => {}
+                _ => anyhow::bail!(
+                    "{} in {} needs both a latitude and a longitude",
+                    name,
+                    path.display()
+                ),
+            }
+            if let Some(max) = entry.max_international {
+                participant = participant.max_international(max);
+            }
+            if let Some(mix) = entry.international {
+                participant = participant.international_mix(mix.parse().with_context(|| {
+                    format!(
+                        "Invalid international mix for {} in {}",
+                        name,
+                        path.display()
+                    )
+                })?);
+            }
+            if let Some(interests) = entry.interests {
+                participant = participant.interests(
+                    interests
+                        .split(';')
+                        .map(|interest| interest.trim().to_string())
+                        .filter(|interest| !interest.is_empty())
+                        .collect(),
+                );
+            }
+            Ok(participant)
+        })
+        .collect()
+}
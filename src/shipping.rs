@@ -0,0 +1,180 @@
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::Participant;
+
+/// What sending a card from one participant to another costs.
+///
+/// The cost of a pair is the entry for their two countries in `country_costs` if there is one,
+/// and otherwise `domestic` or `international` depending on whether they share a country. When
+/// both have coordinates, `per_km` times the distance between them is added on top.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShippingCosts {
+    /// `(from, to, cost)` by country code.
+    pub country_costs: Vec<(String, String, f64)>,
+    pub domestic: f64,
+    pub international: f64,
+    pub per_km: f64,
+}
+
+impl Default for ShippingCosts {
+    fn default() -> Self {
+        ShippingCosts {
+            country_costs: Vec::new(),
+            domestic: 1.,
+            international: 3.,
+            per_km: 0.,
+        }
+    }
+}
+
+impl ShippingCosts {
+    /// The cost of `sender` sending a card to `receiver`.
+    pub fn cost(&self, sender: &Participant, receiver: &Participant) -> f64 {
+        let base = match (&sender.country, &receiver.country) {
+            (Some(from), Some(to)) => self
+                .country_costs
+                .iter()
+                .find(|(f, t, _)| f.eq_ignore_ascii_case(from) && t.eq_ignore_ascii_case(to))
+                .map_or_else(
+                    || {
+                        if from.eq_ignore_ascii_case(to) {
+                            self.domestic
+                        } else {
+                            self.international
+                        }
+                    },
+                    |&(_, _, cost)| cost,
+                ),
+            _ => self.domestic,
+        };
+        let distance = match (sender.location, receiver.location) {
+            (Some(from), Some(to)) => distance_km(from, to),
+            _ => 0.,
+        };
+        base + self.per_km * distance
+    }
+}
+
+/// Where a participant wants their cards to come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InternationalMix {
+    /// Anywhere.
+    #[default]
+    Any,
+    /// At least this many from other countries.
+    AtLeast(u32),
+    /// Only from their own country.
+    DomesticOnly,
+}
+
+impl fmt::Display for InternationalMix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InternationalMix::Any => write!(f, "any"),
+            InternationalMix::AtLeast(k) => write!(f, "{}", k),
+            InternationalMix::DomesticOnly => write!(f, "domestic"),
+        }
+    }
+}
+
+impl FromStr for InternationalMix {
+    type Err = anyhow::Error;
+
+    /// Parses `any`, `domestic` (or `domestic-only`), or the fewest cards from abroad as a number.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "any" => Ok(InternationalMix::Any),
+            "domestic" | "domestic-only" => Ok(InternationalMix::DomesticOnly),
+            other => other
+                .parse()
+                .map(InternationalMix::AtLeast)
+                .map_err(|_| anyhow::anyhow!("Unknown international mix: {}", other)),
+        }
+    }
+}
+
+/// Whether a card from `sender` to `receiver` crosses a border. Unknown countries count as
+/// domestic.
+pub fn is_international(sender: &Participant, receiver: &Participant) -> bool {
+    match (&sender.country, &receiver.country) {
+        (Some(from), Some(to)) => !from.eq_ignore_ascii_case(to),
+        _ => false,
+    }
+}
+
+/// The great-circle distance between two `(latitude, longitude)` points in degrees.
+pub fn distance_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.;
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let a = ((lat2 - lat1) / 2.).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.).sin().powi(2);
+    2. * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// One row of a country cost file.
+#[derive(Debug, Deserialize)]
+struct CountryCostRecord {
+    from: String,
+    to: String,
+    cost: f64,
+}
+
+/// Reads a CSV file of costs between countries, with `from`, `to` and `cost` columns, into the
+/// default [`ShippingCosts`].
+pub fn load_shipping_costs(path: impl AsRef<Path>) -> Result<ShippingCosts> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let records: Vec<CountryCostRecord> = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(file)
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(ShippingCosts {
+        country_costs: records
+            .into_iter()
+            .map(|record| (record.from, record.to, record.cost))
+            .collect(),
+        ..ShippingCosts::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn international_mix_parses_names_and_counts() {
+        assert_eq!(
+            "".parse::<InternationalMix>().unwrap(),
+            InternationalMix::Any
+        );
+        assert_eq!(
+            "Domestic-Only".parse::<InternationalMix>().unwrap(),
+            InternationalMix::DomesticOnly
+        );
+        assert_eq!(
+            "2".parse::<InternationalMix>().unwrap(),
+            InternationalMix::AtLeast(2)
+        );
+        assert!("lots".parse::<InternationalMix>().is_err());
+    }
+
+    #[test]
+    fn international_mix_displays_as_it_parses() {
+        for mix in [
+            InternationalMix::Any,
+            InternationalMix::AtLeast(3),
+            InternationalMix::DomesticOnly,
+        ] {
+            assert_eq!(mix.to_string().parse::<InternationalMix>().unwrap(), mix);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use russcip::Status;
+
+use crate::{ObjectiveValue, Participant, short_cycles, strongly_connected_components};
+
+/// Statistics reported by SCIP for a solve.
+#[derive(Debug, Clone)]
+pub struct SolveStats {
+    /// [`Status::Optimal`], or why the search stopped early if a limit was reached.
+    pub status: Status,
+    pub objective: f64,
+    /// The best objective value SCIP proved possible, for the same level as [`Self::objective`].
+    pub dual_bound: f64,
+    /// The relative gap between [`Self::objective`] and [`Self::dual_bound`]; 0 when optimal.
+    pub gap: f64,
+    /// What the greedy pairing the solver started from scores, for the same level as
+    /// [`Self::objective`]. `None` if it broke a rule, so the solver could not start from it.
+    pub greedy_objective: Option<f64>,
+    pub solving_time: f64,
+    pub n_nodes: usize,
+    pub n_vars: usize,
+    pub n_conss: usize,
+    /// The value reached at each level of the objective, in priority order.
+    pub levels: Vec<ObjectiveValue>,
+}
+
+impl SolveStats {
+    /// Whether every objective level was solved to optimality.
+    pub fn is_optimal(&self) -> bool {
+        self.status == Status::Optimal
+    }
+}
+
+/// The result of solving a [`crate::PairingProblem`].
+#[derive(Debug, Clone)]
+pub struct PairingSolution {
+    pub participants: Vec<Participant>,
+    /// `(sender, receiver)` pairs, as participant indices.
+    pub pairings: Vec<(usize, usize)>,
+    pub stats: SolveStats,
+}
+
+impl PairingSolution {
+    /// The participants `sender` sends a card to.
+    pub fn sent_by(&self, sender: usize) -> Vec<usize> {
+        self.pairings
+            .iter()
+            .filter(|(i, _)| *i == sender)
+            .map(|(_, j)| *j)
+            .collect()
+    }
+
+    /// The participants `receiver` receives a card from.
+    pub fn received_by(&self, receiver: usize) -> Vec<usize> {
+        self.pairings
+            .iter()
+            .filter(|(_, j)| *j == receiver)
+            .map(|(i, _)| *i)
+            .collect()
+    }
+
+    /// For each participant, the fraction of the most cards they asked to receive that they do
+    /// receive, or `None` if they asked for none.
+    pub fn fulfillment_ratios(&self) -> Vec<Option<f64>> {
+        self.participants
+            .iter()
+            .enumerate()
+            .map(|(i, participant)| {
+                let requested = participant.receive_range().max;
+                (requested > 0).then(|| self.received_by(i).len() as f64 / requested as f64)
+            })
+            .collect()
+    }
+
+    /// Pairings in this solution that `other` does not use.
+    pub fn pairings_not_in(&self, other: &PairingSolution) -> Vec<(usize, usize)> {
+        self.pairings
+            .iter()
+            .copied()
+            .filter(|&(i, j)| !other.has_pairing(i, j))
+            .collect()
+    }
+
+    /// How many pairs differ between this solution and `other`, counting pairs used by either one
+    /// but not both.
+    pub fn distance(&self, other: &PairingSolution) -> usize {
+        self.pairings_not_in(other).len() + other.pairings_not_in(self).len()
+    }
+
+    /// The length of the shortest exchange cycle, if there is one.
+    pub fn shortest_cycle(&self) -> Option<usize> {
+        short_cycles(
+            self.participants.len(),
+            |i, j| self.has_pairing(i, j),
+            self.participants.len() + 1,
+        )
+        .iter()
+        .map(|cycle| cycle.len())
+        .min()
+    }
+
+    /// Groups of participants who can all reach each other by following sent cards. In
+    /// single-ring mode, these are the rings (plus anyone sitting out, on their own).
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        strongly_connected_components(self.participants.len(), |i, j| self.has_pairing(i, j))
+    }
+
+    pub fn has_pairing(&self, sender: usize, receiver: usize) -> bool {
+        self.pairings.contains(&(sender, receiver))
+    }
+}
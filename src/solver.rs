@@ -0,0 +1,1190 @@
+use anyhow::Result;
+use russcip::{
+    HeurTiming, Model, ModelWithProblem, ObjSense, ProblemCreated, ProblemOrSolving, Solution,
+    Solving, Status, VarType, Variable, WithSolutions, WithSolvingStats,
+};
+
+use crate::cycles::{ShortCycleConshdlr, StrongConnectivityConshdlr, SubtourConshdlr};
+use crate::heuristic::{RoundingHeuristic, greedy_pairings};
+use crate::interrupt::{InterruptEventhdlr, catches_ctrl_c, interrupt_requested, reset_interrupt};
+use crate::objective::shuffle_weight;
+use crate::progress::{self, ProgressEventhdlr};
+use crate::{
+    CoveragePriority, HistoryMode, InternationalMix, Objective, ObjectiveLevel, ObjectiveValue,
+    PairingProblem, PairingSolution, SolveLimits, SolveStats, strongly_connected_components,
+};
+
+/// Slack allowed when locking in the optimum of an earlier objective, to absorb rounding in
+/// SCIP's reported objective value.
+const LOCK_TOLERANCE: f64 = 1e-6;
+
+/// Runs the rounding heuristic before SCIP's own LP rounding heuristics, whose priorities are
+/// negative.
+const ROUNDING_PRIORITY: i32 = 1000;
+/// How often, in nodes, the rounding heuristic runs.
+const ROUNDING_FREQUENCY: i32 = 10;
+
+/// The SCIP model for a [`PairingProblem`], with the variables needed to read solutions back.
+struct PairingModel {
+    model: Model<ProblemCreated>,
+    /// x[i][j] is 1 if person i sends a card to person j
+    x: Vec<Vec<Pair>>,
+    /// shortfall[i] is how many cards person i receives below their minimum. Only present in the
+    /// elastic model used to explain infeasible minimums.
+    shortfall: Vec<Option<Variable>>,
+    /// Rules that pairs fixed in advance already break, so that no solution can exist.
+    broken: Vec<&'static str>,
+    /// Variables besides `x` that solutions need values for.
+    helpers: HelperVars,
+}
+
+impl PairingModel {
+    /// Offers `pairings` to SCIP as a starting solution, with the other variables set to match.
+    /// Returns whether SCIP took it: it is dropped if it breaks any constraint.
+    fn add_start(&self, problem: &PairingProblem, pairings: &[(usize, usize)]) -> bool {
+        let sol = self.model.create_orig_sol();
+        self.helpers.set_solution(&sol, problem, &self.x, pairings);
+        self.model.add_sol(sol).is_ok()
+    }
+}
+
+/// The variables of a [`PairingModel`] that follow from which pairs are used, so that a pairing
+/// built outside SCIP can be turned into a complete solution.
+#[derive(Clone)]
+pub(crate) struct HelperVars {
+    /// The lowest fraction of their request that anyone receives, when fairness is optimized.
+    min_ratio: Option<Variable>,
+    /// covered[k] is 1 only if the k-th receive-only person gets a card.
+    covered: Vec<Variable>,
+    /// leaders[i] is 1 if person i leads their ring. One entry per participant, only present in
+    /// single-ring mode for people taking part.
+    leaders: Vec<Option<Variable>>,
+}
+
+impl HelperVars {
+    /// Sets `sol` to use exactly `pairings`, with the helper variables to match.
+    pub(crate) fn set_solution(
+        &self,
+        sol: &Solution,
+        problem: &PairingProblem,
+        x: &[Vec<Pair>],
+        pairings: &[(usize, usize)],
+    ) {
+        let used = used_pairs(problem.num_participants(), pairings);
+        for (i, row) in x.iter().enumerate() {
+            for (j, pair) in row.iter().enumerate() {
+                if let Pair::Free(var) = pair {
+                    sol.set_val(var, f64::from(u8::from(used[i][j])));
+                }
+            }
+        }
+        if let Some(min_ratio) = &self.min_ratio {
+            sol.set_val(min_ratio, min_fulfillment(problem, pairings));
+        }
+        let received = received_counts(problem.num_participants(), pairings);
+        for (&i, var) in problem.receive_only().iter().zip(&self.covered) {
+            sol.set_val(var, f64::from(u8::from(received[i] > 0)));
+        }
+        for i in ring_leaders(problem, &used) {
+            if let Some(leader) = &self.leaders[i] {
+                sol.set_val(leader, 1.);
+            }
+        }
+    }
+}
+
+/// Whether one person sends a card to another: decided by the solver, or fixed in advance when
+/// appending to an existing pairing.
+#[derive(Clone)]
+pub(crate) enum Pair {
+    Free(Variable),
+    Fixed(bool),
+}
+
+impl Pair {
+    pub(crate) fn value(&self, sol: &Solution) -> f64 {
+        match self {
+            Pair::Free(var) => sol.val(var),
+            Pair::Fixed(used) => f64::from(u8::from(*used)),
+        }
+    }
+
+    /// The value in the LP solution SCIP is currently working on.
+    pub(crate) fn current_value(&self, model: &Model<Solving>) -> f64 {
+        match self {
+            Pair::Free(var) => model.current_val(var),
+            Pair::Fixed(used) => f64::from(u8::from(*used)),
+        }
+    }
+
+    fn is_used(&self, sol: &Solution) -> bool {
+        self.value(sol) >= 0.9
+    }
+}
+
+/// A linear expression over pairs and other variables, where fixed pairs become a constant.
+#[derive(Default)]
+struct LinExpr<'a> {
+    vars: Vec<&'a Variable>,
+    coefs: Vec<f64>,
+    constant: f64,
+}
+
+impl<'a> LinExpr<'a> {
+    fn pair(mut self, pair: &'a Pair, coef: f64) -> Self {
+        match pair {
+            Pair::Free(var) => {
+                self.vars.push(var);
+                self.coefs.push(coef);
+            }
+            Pair::Fixed(true) => self.constant += coef,
+            Pair::Fixed(false) => {}
+        }
+        self
+    }
+
+    fn pairs(self, pairs: impl IntoIterator<Item = &'a Pair>, coef: f64) -> Self {
+        pairs
+            .into_iter()
+            .fold(self, |expr, pair| expr.pair(pair, coef))
+    }
+
+    fn var(mut self, var: &'a Variable, coef: f64) -> Self {
+        self.vars.push(var);
+        self.coefs.push(coef);
+        self
+    }
+}
+
+/// Adds `lhs <= expr <= rhs` to the model. A constraint on fixed pairs alone is checked right
+/// away instead, and recorded in `broken` if it does not hold.
+fn add_cons(
+    model: &mut Model<ProblemCreated>,
+    broken: &mut Vec<&'static str>,
+    expr: LinExpr,
+    lhs: f64,
+    rhs: f64,
+    name: &'static str,
+) {
+    if expr.vars.is_empty() {
+        if (expr.constant < lhs - LOCK_TOLERANCE || expr.constant > rhs + LOCK_TOLERANCE)
+            && !broken.contains(&name)
+        {
+            broken.push(name);
+        }
+        return;
+    }
+    model.add_cons(
+        expr.vars,
+        &expr.coefs,
+        lhs - expr.constant,
+        rhs - expr.constant,
+        name,
+    );
+}
+
+/// What a model is built to optimize.
+enum Goal<'a> {
+    /// Maximize `objective`, keeping each locked level within its tolerance of its value.
+    Optimize {
+        objective: Objective,
+        locked: &'a [(ObjectiveLevel, f64)],
+    },
+    /// Allow minimum card counts to be missed, and minimize the total shortfall.
+    MinimizeShortfall,
+}
+
+/// Pairings that a model must differ from, each by at least `min_difference` pairs.
+#[derive(Default)]
+struct Alternatives<'a> {
+    previous: &'a [Vec<(usize, usize)>],
+    min_difference: usize,
+}
+
+/// Solves `problem`, optimizing the objectives of its [`crate::ObjectiveMode`] in turn. If one of
+/// its [`SolveLimits`] stops the search early, returns the best pairing found by then, with the
+/// reason in its [`SolveStats::status`].
+pub fn generate_pairings(problem: &PairingProblem) -> Result<PairingSolution> {
+    check_required_pairs(problem)?;
+    check_international_mix(problem)?;
+    reset_interrupt();
+    let solution = solve_levels(problem, &Alternatives::default())?;
+    Ok(solution.expect("a problem without alternatives to avoid is never cut off"))
+}
+
+/// Solves `problem` up to `count` times, each time for the best pairing that differs from all
+/// the ones before it in at least `min_difference` pairs. Returns fewer than `count` solutions if
+/// no further pairing is different enough, or if the solve is interrupted.
+pub fn generate_distinct_pairings(
+    problem: &PairingProblem,
+    count: usize,
+    min_difference: usize,
+) -> Result<Vec<PairingSolution>> {
+    check_required_pairs(problem)?;
+    check_international_mix(problem)?;
+    reset_interrupt();
+    let mut previous = Vec::new();
+    let mut solutions = Vec::new();
+    while solutions.len() < count {
+        if interrupt_requested() && !solutions.is_empty() {
+            break;
+        }
+        let alternatives = Alternatives {
+            previous: &previous,
+            min_difference,
+        };
+        let Some(solution) = solve_levels(problem, &alternatives)? else {
+            break;
+        };
+        previous.push(solution.pairings.clone());
+        solutions.push(solution);
+    }
+    Ok(solutions)
+}
+
+fn check_required_pairs(problem: &PairingProblem) -> Result<()> {
+    let conflicts = problem.required_pair_conflicts();
+    if !conflicts.is_empty() {
+        let reasons: Vec<String> = conflicts.iter().map(|c| c.to_string()).collect();
+        anyhow::bail!("Required pairs conflict:\n  {}", reasons.join("\n  "));
+    }
+    Ok(())
+}
+
+/// Rejects targets for cards from abroad set for someone without a country: unknown countries
+/// count as domestic, so they can never get any.
+fn check_international_mix(problem: &PairingProblem) -> Result<()> {
+    let names: Vec<&str> = problem
+        .participants()
+        .iter()
+        .filter(|participant| {
+            participant.country.is_none()
+                && matches!(participant.international_mix, InternationalMix::AtLeast(k) if k > 0)
+        })
+        .map(|participant| participant.name.as_str())
+        .collect();
+    if !names.is_empty() {
+        anyhow::bail!(
+            "Cards from abroad were requested for participants without a country: {}",
+            names.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Optimizes each objective level of `problem` in turn. Returns `None` if no pairing differs
+/// enough from the `alternatives` to avoid.
+fn solve_levels(
+    problem: &PairingProblem,
+    alternatives: &Alternatives,
+) -> Result<Option<PairingSolution>> {
+    let mut locked: Vec<(ObjectiveLevel, f64)> = Vec::new();
+    let mut solving_time = 0.;
+    let mut n_nodes = 0;
+    let mut objective_value = 0.;
+    let mut result: Option<PairingSolution> = None;
+    let mut levels = problem.objective_mode().levels();
+    if levels.is_empty() {
+        anyhow::bail!("A lexicographic objective needs at least one level");
+    }
+    if problem.coverage() == CoveragePriority::First && !problem.receive_only().is_empty() {
+        levels.insert(0, ObjectiveLevel::new(Objective::Coverage));
+    }
+    if problem.is_single_ring() {
+        // A single ring comes first; failing that, as few rings as possible.
+        levels.insert(0, ObjectiveLevel::new(Objective::FewestRings));
+    }
+    if problem.minimizes_shipping() {
+        levels.push(ObjectiveLevel::new(Objective::ShippingCost));
+    }
+    if problem.random_seed().is_some() {
+        levels.push(ObjectiveLevel::new(Objective::Shuffle));
+    }
+    if weight_is_card_count(problem) {
+        // Maximizing the weight right after the card count would only solve the same model again.
+        levels.dedup_by(|level, previous| {
+            previous.objective == Objective::TotalCards
+                && previous.tolerance == 0.
+                && level.objective == Objective::TotalWeight
+        });
+    }
+    let greedy = greedy_pairings(problem);
+    let mut greedy_feasible = false;
+    let mut greedy_value = None;
+    let limits = problem.limits();
+    let mut status = Status::Optimal;
+    let mut dual_bound = 0.;
+    let mut gap = 0.;
+    for level in levels {
+        if interrupt_requested()
+            && let Some(solution) = &mut result
+        {
+            // Settle for the levels optimized so far.
+            if solution.stats.is_optimal() {
+                solution.stats.status = Status::UserInterrupt;
+            }
+            break;
+        }
+        let objective = level.objective;
+        let pairing_model = build_model(
+            problem,
+            Goal::Optimize {
+                objective,
+                locked: &locked,
+            },
+            alternatives,
+        );
+
+        if !pairing_model.broken.is_empty() {
+            anyhow::bail!(
+                "The existing pairings break these rules on their own: {}",
+                pairing_model.broken.join(", ")
+            );
+        }
+
+        // Start from the greedy pairing, or from the previous level's optimum, which meets
+        // every locked objective.
+        match &result {
+            Some(solution) => {
+                pairing_model.add_start(problem, &solution.pairings);
+            }
+            None => greedy_feasible = pairing_model.add_start(problem, &greedy),
+        }
+        let PairingModel { model, x, .. } = pairing_model;
+
+        // SCIP only sees the free pairs, so add what the fixed ones contribute.
+        let mut fixed_value = 0.;
+        for (i, row) in x.iter().enumerate() {
+            for (j, pair) in row.iter().enumerate() {
+                if let Pair::Fixed(true) = pair {
+                    fixed_value += pair_coefficient(problem, objective, i, j);
+                }
+            }
+        }
+
+        let mut model = model;
+        if let Some(callback) = problem.progress_callback() {
+            model.include_eventhdlr(
+                "progress",
+                "Reports each new best pairing",
+                Box::new(ProgressEventhdlr {
+                    x: x.clone(),
+                    level: locked.len(),
+                    objective,
+                    fixed_value,
+                    elapsed: solving_time,
+                    callback: callback.clone(),
+                }),
+            );
+        }
+
+        let n_vars = model.n_vars();
+        let n_conss = model.n_conss();
+        let model = apply_limits(model, limits, limits.time.map(|time| time - solving_time))?;
+        let solved_model = model.solve();
+        let level_status = solved_model.status();
+        if level_status == Status::Infeasible && !alternatives.previous.is_empty() {
+            return Ok(None);
+        }
+        if !stopped_early(level_status) {
+            check_status(problem, level_status)?;
+        }
+        solving_time += solved_model.solving_time();
+        n_nodes += solved_model.n_nodes();
+
+        let Some(sol) = solved_model.best_sol() else {
+            // Out of time or nodes before finding anything: fall back on the earlier levels.
+            let Some(mut solution) = result else {
+                anyhow::bail!(
+                    "No pairing found before the solve stopped (status: {:?})",
+                    level_status
+                );
+            };
+            solution.stats.status = level_status;
+            return Ok(Some(solution));
+        };
+        if status == Status::Optimal {
+            status = level_status;
+        }
+
+        let mut pairings: Vec<(usize, usize)> = Vec::new();
+        for (i, row) in x.iter().enumerate() {
+            for (j, pair) in row.iter().enumerate() {
+                if pair.is_used(&sol) {
+                    pairings.push((i, j));
+                }
+            }
+        }
+
+        let value = solved_model.obj_val() + fixed_value;
+        locked.push((level, value));
+        // The shuffle level only picks among equals, so it does not count as the objective.
+        if objective != Objective::Shuffle {
+            objective_value = value;
+            dual_bound = solved_model.best_bound() + fixed_value;
+            gap = progress::gap(&solved_model);
+            greedy_value = greedy_feasible.then(|| pairing_value(problem, objective, &greedy));
+        }
+        let levels = locked
+            .iter()
+            .map(|&(level, value)| ObjectiveValue {
+                objective: level.objective,
+                value,
+            })
+            .collect();
+        result = Some(PairingSolution {
+            participants: problem.participants().to_vec(),
+            pairings,
+            stats: SolveStats {
+                status,
+                objective: objective_value,
+                dual_bound,
+                gap,
+                greedy_objective: greedy_value,
+                solving_time,
+                n_nodes,
+                n_vars,
+                n_conss,
+                levels,
+            },
+        });
+    }
+
+    Ok(Some(result.expect("at least one level was optimized")))
+}
+
+/// Passes `limits` on to SCIP, with `time` the seconds left for this model. Fails if SCIP rejects
+/// a limit, e.g. a negative gap.
+fn apply_limits(
+    mut model: Model<ProblemCreated>,
+    limits: SolveLimits,
+    time: Option<f64>,
+) -> Result<Model<ProblemCreated>> {
+    if let Some(time) = time {
+        model = model
+            .set_real_param("limits/time", time.max(0.))
+            .map_err(|code| anyhow::anyhow!("Invalid time limit {}: {:?}", time, code))?;
+    }
+    if let Some(gap) = limits.gap {
+        model = model
+            .set_real_param("limits/gap", gap)
+            .map_err(|code| anyhow::anyhow!("Invalid gap limit {}: {:?}", gap, code))?;
+    }
+    if let Some(nodes) = limits.nodes {
+        model = model
+            .set_longint_param("limits/nodes", nodes.min(i64::MAX as u64) as i64)
+            .map_err(|code| anyhow::anyhow!("Invalid node limit {}: {:?}", nodes, code))?;
+    }
+    Ok(model)
+}
+
+/// Whether SCIP stopped at a limit or on request, rather than finishing the search. The best
+/// pairing found by then is still valid, just not proven optimal.
+fn stopped_early(status: Status) -> bool {
+    matches!(
+        status,
+        Status::UserInterrupt
+            | Status::NodeLimit
+            | Status::TotalNodeLimit
+            | Status::StallNodeLimit
+            | Status::TimeLimit
+            | Status::MemoryLimit
+            | Status::GapLimit
+            | Status::SolutionLimit
+            | Status::BestSolutionLimit
+            | Status::RestartLimit
+    )
+}
+
+/// Turns a solve that did not reach optimality into an error that explains why, where possible.
+fn check_status(problem: &PairingProblem, status: Status) -> Result<()> {
+    match status {
+        Status::Optimal => Ok(()),
+        Status::Infeasible if has_min_cards(problem) => {
+            let short = participants_short_of_minimum(problem)?;
+            if !short.is_empty() {
+                anyhow::bail!(
+                    "Minimum card counts cannot all be met; the closest pairing leaves {}",
+                    short.join(", ")
+                );
+            }
+            anyhow::bail!("Optimal solution not found (status: Infeasible)");
+        }
+        Status::Infeasible if !problem.required_pairs().is_empty() => {
+            if !has_pairing(&problem.without_required_pairs()) {
+                anyhow::bail!(
+                    "Optimal solution not found (status: Infeasible), even without the required \
+                     pairs"
+                );
+            }
+            let participants = problem.participants();
+            let pairs: Vec<String> = problem
+                .required_pairs()
+                .iter()
+                .map(|&(i, j)| format!("{} -> {}", participants[i].name, participants[j].name))
+                .collect();
+            anyhow::bail!(
+                "No valid pairing contains all required pairs ({}), though there are valid \
+                 pairings without them",
+                pairs.join(", ")
+            );
+        }
+        status => anyhow::bail!("Optimal solution not found (status: {:?})", status),
+    }
+}
+
+/// Whether `problem` has any valid pairing at all, whatever its objective.
+fn has_pairing(problem: &PairingProblem) -> bool {
+    let goal = Goal::Optimize {
+        objective: Objective::TotalCards,
+        locked: &[],
+    };
+    let pairing_model = build_model(problem, goal, &Alternatives::default());
+    pairing_model.broken.is_empty() && pairing_model.model.solve().best_sol().is_some()
+}
+
+fn has_min_cards(problem: &PairingProblem) -> bool {
+    (0..problem.num_participants()).any(|i| problem.min_cards_for(i) > 0)
+}
+
+/// Explains infeasible minimums: solves a relaxed model where minimums may be missed, keeping the
+/// total shortfall as small as possible, and describes who falls short there.
+fn participants_short_of_minimum(problem: &PairingProblem) -> Result<Vec<String>> {
+    let PairingModel {
+        model, shortfall, ..
+    } = build_model(problem, Goal::MinimizeShortfall, &Alternatives::default());
+    let solved_model = model.solve();
+    let Some(sol) = solved_model.best_sol() else {
+        return Ok(Vec::new());
+    };
+
+    let mut short = Vec::new();
+    for (i, var) in shortfall.iter().enumerate() {
+        let Some(var) = var else { continue };
+        let missing = sol.val(var).round() as u32;
+        if missing > 0 {
+            let min = problem.min_cards_for(i);
+            short.push(format!(
+                "{} with {} of their minimum {} cards",
+                problem.participants()[i].name,
+                min - missing,
+                min
+            ));
+        }
+    }
+    Ok(short)
+}
+
+/// The coefficient of x[i][j] in `objective`.
+fn pair_coefficient(problem: &PairingProblem, objective: Objective, i: usize, j: usize) -> f64 {
+    match objective {
+        Objective::TotalWeight => problem.objective_weight(i, j),
+        Objective::TotalCards => 1.,
+        Objective::MinFulfillment => 0.,
+        Objective::Preference => problem.preference(i, j),
+        Objective::FewestRepeats => {
+            if problem.is_repeat(i, j) {
+                -1.
+            } else {
+                0.
+            }
+        }
+        Objective::ShippingCost => -problem.shipping_cost(i, j),
+        Objective::Coverage | Objective::FewestRings => 0.,
+        Objective::Shuffle => problem
+            .random_seed()
+            .map_or(0., |seed| shuffle_weight(seed, i, j)),
+    }
+}
+
+/// The value of `objective` for `pairings`, as SCIP would count it.
+fn pairing_value(
+    problem: &PairingProblem,
+    objective: Objective,
+    pairings: &[(usize, usize)],
+) -> f64 {
+    let pairs: f64 = pairings
+        .iter()
+        .map(|&(i, j)| pair_coefficient(problem, objective, i, j))
+        .sum();
+    let received = received_counts(problem.num_participants(), pairings);
+    let covered = problem
+        .receive_only()
+        .iter()
+        .filter(|&&i| received[i] > 0)
+        .count();
+    let mut value = pairs + coverage_coefficient(problem, objective) * covered as f64;
+    match objective {
+        Objective::MinFulfillment => value += min_fulfillment(problem, pairings),
+        Objective::FewestRings => {
+            let used = used_pairs(problem.num_participants(), pairings);
+            value -= ring_leaders(problem, &used).len() as f64;
+        }
+        _ => {}
+    }
+    value
+}
+
+/// used[i][j] is whether `pairings` has `i` send to `j`.
+fn used_pairs(n: usize, pairings: &[(usize, usize)]) -> Vec<Vec<bool>> {
+    let mut used = vec![vec![false; n]; n];
+    for &(i, j) in pairings {
+        used[i][j] = true;
+    }
+    used
+}
+
+/// How many cards each participant receives in `pairings`.
+fn received_counts(n: usize, pairings: &[(usize, usize)]) -> Vec<u32> {
+    let mut received = vec![0; n];
+    for &(_, j) in pairings {
+        received[j] += 1;
+    }
+    received
+}
+
+/// The lowest fraction of their request that anyone who asked for cards receives.
+fn min_fulfillment(problem: &PairingProblem, pairings: &[(usize, usize)]) -> f64 {
+    let received = received_counts(problem.num_participants(), pairings);
+    problem
+        .participants()
+        .iter()
+        .zip(received)
+        .filter(|(participant, _)| participant.receive_range().max > 0)
+        .map(|(participant, received)| received as f64 / participant.receive_range().max as f64)
+        .fold(1., f64::min)
+}
+
+/// One leader for each ring among the `used` pairs: its first member who sends cards.
+fn ring_leaders(problem: &PairingProblem, used: &[Vec<bool>]) -> Vec<usize> {
+    strongly_connected_components(problem.num_participants(), |i, j| used[i][j])
+        .into_iter()
+        .filter(|members| members.len() > 1)
+        .filter_map(|members| {
+            members
+                .into_iter()
+                .find(|&i| problem.participants()[i].send_range().max > 0)
+        })
+        .collect()
+}
+
+/// Whether [`Objective::TotalWeight`] just counts cards: every pair weighs 1, and covering
+/// receive-only participants adds nothing.
+fn weight_is_card_count(problem: &PairingProblem) -> bool {
+    let n = problem.num_participants();
+    (problem.receive_only().is_empty()
+        || coverage_coefficient(problem, Objective::TotalWeight) == 0.)
+        && (0..n).all(|i| (0..n).all(|j| problem.objective_weight(i, j) == 1.))
+}
+
+/// The coefficient of each receive-only participant's coverage in `objective`.
+fn coverage_coefficient(problem: &PairingProblem, objective: Objective) -> f64 {
+    match (objective, problem.coverage()) {
+        (Objective::Coverage, _) => 1.,
+        (Objective::TotalWeight, CoveragePriority::Weighted(weight)) => weight,
+        _ => 0.,
+    }
+}
+
+/// Turns off SCIP's dual reductions, for models with a lazy constraint handler. Its cuts only
+/// show up once a solution breaks them, and russcip does not let it lock the variables they will
+/// use, so SCIP would otherwise be free to fix those variables to whatever the objective prefers.
+fn without_dual_reductions(model: Model<ProblemCreated>) -> Model<ProblemCreated> {
+    model
+        .set_bool_param("misc/allowstrongdualreds", false)
+        .and_then(|model| model.set_bool_param("misc/allowweakdualreds", false))
+        .expect("Failed to turn off dual reductions")
+}
+
+/// Builds the SCIP model for `problem`.
+fn build_model(problem: &PairingProblem, goal: Goal, alternatives: &Alternatives) -> PairingModel {
+    let n = problem.num_participants();
+
+    let mut model = Model::new()
+        .hide_output()
+        .include_default_plugins()
+        .create_prob("pairings")
+        .set_obj_sense(ObjSense::Maximize);
+    if let Some(seed) = problem.random_seed() {
+        // Also vary SCIP's own random choices, which otherwise always start from the same seed.
+        model = model
+            .set_int_param(
+                "randomization/randomseedshift",
+                (seed % i32::MAX as u64) as i32,
+            )
+            .expect("Failed to set random seed");
+    }
+
+    if catches_ctrl_c() {
+        // Ctrl-C is ours to handle, so that it also stops the levels and alternatives to come.
+        model = model
+            .set_bool_param("misc/catchctrlc", false)
+            .expect("Failed to turn off SCIP's Ctrl-C handling");
+    }
+    model.include_eventhdlr(
+        "interrupt",
+        "Stops solving when an interrupt is requested",
+        Box::new(InterruptEventhdlr),
+    );
+
+    let (objective, locked) = match goal {
+        Goal::Optimize { objective, locked } => (Some(objective), locked),
+        Goal::MinimizeShortfall => (None, &[][..]),
+    };
+    let uses = |o: Objective| objective == Some(o) || locked.iter().any(|(l, _)| l.objective == o);
+
+    // x[i][j] is 1 if person i sends a card to person j
+    let mut x = Vec::new();
+    for i in 0..n {
+        let mut row = Vec::new();
+        for j in 0..n {
+            let pair = match problem.fixed_pair(i, j) {
+                Some(used) => Pair::Fixed(used),
+                None => {
+                    let obj = objective.map_or(0., |o| pair_coefficient(problem, o, i, j));
+                    Pair::Free(model.add_var(0., 1., obj, "adjacency", VarType::Binary))
+                }
+            };
+            row.push(pair);
+        }
+        x.push(row);
+    }
+    let received = |i: usize| x.iter().map(move |row| &row[i]);
+    let mut broken = Vec::new();
+
+    // min_ratio is at most the fraction of their request that anyone receives.
+    let min_ratio = uses(Objective::MinFulfillment).then(|| {
+        let obj = if objective == Some(Objective::MinFulfillment) {
+            1.
+        } else {
+            0.
+        };
+        model.add_var(0., 1., obj, "min_ratio", VarType::Continuous)
+    });
+    if let Some(min_ratio) = &min_ratio {
+        for (i, participant) in problem.participants().iter().enumerate() {
+            let requested = participant.receive_range().max;
+            if requested == 0 {
+                continue;
+            }
+            let expr = LinExpr::default()
+                .pairs(received(i), 1.)
+                .var(min_ratio, -(requested as f64));
+            add_cons(
+                &mut model,
+                &mut broken,
+                expr,
+                0.,
+                f64::INFINITY,
+                "fulfillment_ratio",
+            );
+        }
+    }
+
+    // leaders[i] is 1 if person i leads their ring, in single-ring mode. Everyone sending a card
+    // sends and receives exactly one.
+    let mut leaders = vec![None; n];
+    if problem.is_single_ring() {
+        let obj = if objective == Some(Objective::FewestRings) {
+            -1.
+        } else {
+            0.
+        };
+        for (i, (row, participant)) in x.iter().zip(problem.participants()).enumerate() {
+            if participant.send_range().max == 0 {
+                continue;
+            }
+            let expr = LinExpr::default().pairs(row, 1.);
+            add_cons(&mut model, &mut broken, expr, 1., 1., "single_ring");
+            let leader = model.add_var(0., 1., obj, "ring_leader", VarType::Binary);
+            let expr = LinExpr::default().pairs(received(i), 1.);
+            add_cons(&mut model, &mut broken, expr, 1., 1., "single_ring");
+            leaders[i] = Some(leader);
+        }
+
+        // Anyone taking part is on some ring, and every ring has a leader. Besides cutting off
+        // pairings without one, this keeps SCIP from fixing every leader to 0 on its own.
+        let expr = leaders
+            .iter()
+            .flatten()
+            .fold(LinExpr::default(), |expr, leader| expr.var(leader, 1.));
+        if !expr.vars.is_empty() {
+            add_cons(
+                &mut model,
+                &mut broken,
+                expr,
+                1.,
+                f64::INFINITY,
+                "some_ring_leader",
+            );
+        }
+        model.include_conshdlr(
+            "subtours",
+            "Requires a ring leader in every group that only exchanges among itself",
+            -1,
+            -1,
+            Box::new(SubtourConshdlr {
+                x: x.clone(),
+                leaders: leaders.clone(),
+            }),
+        );
+        model = without_dual_reductions(model);
+    }
+
+    // covered[k] is 1 only if the k-th receive-only person gets at least one card.
+    let receive_only = problem.receive_only();
+    let covered: Vec<Variable> = receive_only
+        .iter()
+        .map(|_| {
+            let obj = objective.map_or(0., |o| coverage_coefficient(problem, o));
+            model.add_var(0., 1., obj, "covered", VarType::Binary)
+        })
+        .collect();
+    for (&i, var) in receive_only.iter().zip(&covered) {
+        let expr = LinExpr::default().var(var, 1.).pairs(received(i), -1.);
+        add_cons(
+            &mut model,
+            &mut broken,
+            expr,
+            -f64::INFINITY,
+            0.,
+            "coverage",
+        );
+    }
+
+    // Earlier objectives stay within their tolerance of the optimum they reached.
+    for &(level, value) in locked {
+        let locked_objective = level.objective;
+        let mut expr = LinExpr::default();
+        for (i, row) in x.iter().enumerate() {
+            for (j, pair) in row.iter().enumerate() {
+                let coef = pair_coefficient(problem, locked_objective, i, j);
+                if coef != 0. {
+                    expr = expr.pair(pair, coef);
+                }
+            }
+        }
+        if let (Objective::MinFulfillment, Some(min_ratio)) = (locked_objective, &min_ratio) {
+            expr = expr.var(min_ratio, 1.);
+        }
+        let coef = coverage_coefficient(problem, locked_objective);
+        if coef != 0. {
+            for var in &covered {
+                expr = expr.var(var, coef);
+            }
+        }
+        if locked_objective == Objective::FewestRings {
+            for leader in leaders.iter().flatten() {
+                expr = expr.var(leader, -1.);
+            }
+        }
+        if expr.vars.is_empty() {
+            continue;
+        }
+        add_cons(
+            &mut model,
+            &mut broken,
+            expr,
+            value - level.tolerance - LOCK_TOLERANCE,
+            f64::INFINITY,
+            "locked_objective",
+        );
+    }
+
+    // No-good cuts: the pairing differs from each earlier one in at least min_difference pairs,
+    // counting both pairs it drops and pairs it adds.
+    for pairings in alternatives.previous {
+        let mut expr = LinExpr::default();
+        for (i, row) in x.iter().enumerate() {
+            for (j, pair) in row.iter().enumerate() {
+                let coef = if pairings.contains(&(i, j)) { -1. } else { 1. };
+                expr = expr.pair(pair, coef);
+            }
+        }
+        add_cons(
+            &mut model,
+            &mut broken,
+            expr,
+            alternatives.min_difference as f64 - pairings.len() as f64,
+            f64::INFINITY,
+            "distinct_solution",
+        );
+    }
+
+    // Nobody sends a card to themself.
+    for (i, row) in x.iter().enumerate() {
+        let expr = LinExpr::default().pair(&row[i], 1.);
+        add_cons(&mut model, &mut broken, expr, 0., 0., "no_self_exchange");
+    }
+
+    // Pairs the organizer has already promised are always used.
+    for &(i, j) in problem.required_pairs() {
+        let expr = LinExpr::default().pair(&x[i][j], 1.);
+        add_cons(&mut model, &mut broken, expr, 1., 1., "required_pair");
+    }
+
+    // Members of an exclusion group never exchange cards with each other.
+    for (i, j) in problem.group_excluded_pairs() {
+        let expr = LinExpr::default().pair(&x[i][j], 1.);
+        add_cons(&mut model, &mut broken, expr, 0., 0., "exclusion_group");
+    }
+
+    // Nobody sends a card along a pair that was explicitly forbidden.
+    for &(i, j) in problem.forbidden_pairs() {
+        let expr = LinExpr::default().pair(&x[i][j], 1.);
+        add_cons(&mut model, &mut broken, expr, 0., 0., "forbidden_pair");
+    }
+
+    // Nobody sends a card to someone they sent one to before, if repeats are forbidden.
+    if problem.history_mode() == HistoryMode::Forbid {
+        for &(i, j, _) in problem.past_pairs() {
+            let expr = LinExpr::default().pair(&x[i][j], 1.);
+            add_cons(&mut model, &mut broken, expr, 0., 0., "no_repeat");
+        }
+    }
+
+    // When appending, existing pairs change in at most max_changes places, counting both pairs
+    // dropped and pairs added between people who were already paired.
+    if let Some(max_changes) = problem.max_changes() {
+        let mut expr = LinExpr::default();
+        let mut existing = 0.;
+        for (i, row) in x.iter().enumerate() {
+            for (j, pair) in row.iter().enumerate() {
+                if problem.is_new(i) || problem.is_new(j) {
+                    continue;
+                }
+                if problem.existing_pairings().contains(&(i, j)) {
+                    expr = expr.pair(pair, -1.);
+                    existing += 1.;
+                } else {
+                    expr = expr.pair(pair, 1.);
+                }
+            }
+        }
+        add_cons(
+            &mut model,
+            &mut broken,
+            expr,
+            -f64::INFINITY,
+            max_changes as f64 - existing,
+            "max_changes",
+        );
+    }
+
+    // Nobody sends more cards abroad than they are willing to.
+    for (i, (row, participant)) in x.iter().zip(problem.participants()).enumerate() {
+        let Some(max) = participant.max_international else {
+            continue;
+        };
+        let abroad = row
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| problem.is_international(i, j))
+            .map(|(_, pair)| pair);
+        let expr = LinExpr::default().pairs(abroad, 1.);
+        add_cons(
+            &mut model,
+            &mut broken,
+            expr,
+            0.,
+            max as f64,
+            "max_international",
+        );
+    }
+
+    // Everyone gets as many cards from abroad as they asked for, or none if they want domestic
+    // ones only.
+    for (j, participant) in problem.participants().iter().enumerate() {
+        let (lhs, rhs) = match participant.international_mix {
+            InternationalMix::Any => continue,
+            InternationalMix::AtLeast(k) => (k as f64, f64::INFINITY),
+            InternationalMix::DomesticOnly => (0., 0.),
+        };
+        let abroad = x
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| problem.is_international(i, j))
+            .map(|(_, row)| &row[j]);
+        let expr = LinExpr::default().pairs(abroad, 1.);
+        add_cons(&mut model, &mut broken, expr, lhs, rhs, "international_mix");
+    }
+
+    // Nobody sends a card to someone who sent a card to them.
+    for (i, row) in x.iter().enumerate() {
+        for (j, pair) in row.iter().enumerate().skip(i + 1) {
+            let expr = LinExpr::default().pair(pair, 1.).pair(&x[j][i], 1.);
+            add_cons(&mut model, &mut broken, expr, 0., 1., "no_mutual_exchange");
+        }
+    }
+
+    // Exchange cycles shorter than the minimum length are cut off as they show up, since there
+    // are far too many to list up front.
+    if problem.min_cycle_length() > 3 {
+        model.include_conshdlr(
+            "short_cycles",
+            "Forbids exchange cycles shorter than the minimum length",
+            -1,
+            -1,
+            Box::new(ShortCycleConshdlr {
+                x: x.clone(),
+                min_length: problem.min_cycle_length(),
+            }),
+        );
+        model = without_dual_reductions(model);
+    }
+
+    // Everyone who sends and receives can reach everyone else who does through the chain of sent
+    // cards. Like short cycles, the groups that break this are cut off as they show up.
+    if problem.requires_strong_connectivity() {
+        model.include_conshdlr(
+            "strong_connectivity",
+            "Requires the exchange graph to be strongly connected",
+            -1,
+            -1,
+            Box::new(StrongConnectivityConshdlr {
+                x: x.clone(),
+                active: problem
+                    .participants()
+                    .iter()
+                    .map(|p| p.send_range().max > 0 && p.receive_range().max > 0)
+                    .collect(),
+            }),
+        );
+        model = without_dual_reductions(model);
+    }
+
+    // Nobody sends more cards than they signed up for, or fewer than they asked to send.
+    for (row, participant) in x.iter().zip(problem.participants()) {
+        let range = participant.send_range();
+        let expr = LinExpr::default().pairs(row, 1.);
+        add_cons(
+            &mut model,
+            &mut broken,
+            expr,
+            range.min as f64,
+            range.max as f64,
+            "num_cards",
+        );
+    }
+
+    // Likewise for the cards they receive. With balance below, this also caps what balanced
+    // people send when their send range goes past `num_cards`.
+    for (i, participant) in problem.participants().iter().enumerate() {
+        let range = participant.receive_range();
+        let expr = LinExpr::default().pairs(received(i), 1.);
+        add_cons(
+            &mut model,
+            &mut broken,
+            expr,
+            range.min as f64,
+            range.max as f64,
+            "receive_cards",
+        );
+    }
+
+    // Everyone who is balanced receives a card for every card they send.
+    for ((i, row), participant) in x.iter().enumerate().zip(problem.participants()) {
+        if !participant.is_balanced() {
+            continue;
+        }
+        // Cards that i sends get a coefficient of +1, and cards that i receives one of -1.
+        let expr = LinExpr::default().pairs(row, 1.).pairs(received(i), -1.);
+        add_cons(&mut model, &mut broken, expr, 0., 0., "card_balance");
+    }
+
+    // Everyone receives at least their minimum number of cards (and so, if they are balanced,
+    // sends at least as many).
+    let mut shortfall = Vec::new();
+    for i in 0..n {
+        let min = problem.min_cards_for(i);
+        if min == 0 {
+            shortfall.push(None);
+            continue;
+        }
+        let slack = matches!(goal, Goal::MinimizeShortfall)
+            .then(|| model.add_var(0., min as f64, -1., "shortfall", VarType::Integer));
+        let mut expr = LinExpr::default().pairs(received(i), 1.);
+        if let Some(slack) = &slack {
+            expr = expr.var(slack, 1.);
+        }
+        add_cons(
+            &mut model,
+            &mut broken,
+            expr,
+            min as f64,
+            f64::INFINITY,
+            "min_cards",
+        );
+        shortfall.push(slack);
+    }
+
+    // Every few nodes, rounds the LP relaxation into a pairing, if asked to. Single rings are
+    // left to SCIP, since rounding rarely gives everyone exactly one card each way.
+    let helpers = HelperVars {
+        min_ratio,
+        covered,
+        leaders,
+    };
+    if problem.uses_rounding_heuristic() && !problem.is_single_ring() {
+        model.include_heur(
+            "pairing_rounding",
+            "Rounds the LP relaxation into a balanced pairing without mutual exchanges",
+            ROUNDING_PRIORITY,
+            'p',
+            ROUNDING_FREQUENCY,
+            0,
+            -1,
+            HeurTiming::AFTER_LP_NODE,
+            false,
+            Box::new(RoundingHeuristic::new(problem, x.clone(), helpers.clone())),
+        );
+    }
+
+    PairingModel {
+        model,
+        x,
+        shortfall,
+        broken,
+        helpers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_leaders_pick_one_sender_per_ring() {
+        let problem = PairingProblem::from_card_counts(&[1; 7]);
+        let pairings = [(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)];
+        let mut leaders = ring_leaders(&problem, &used_pairs(7, &pairings));
+        leaders.sort();
+        assert_eq!(leaders, vec![0, 3]);
+    }
+
+    #[test]
+    fn one_ring_gets_one_leader() {
+        // Five people with three cards each end up in one strongly connected ring.
+        let problem = PairingProblem::from_card_counts(&[3; 5]);
+        let used = used_pairs(5, &greedy_pairings(&problem));
+        assert_eq!(ring_leaders(&problem, &used).len(), 1);
+    }
+
+    #[test]
+    fn min_fulfillment_skips_people_who_asked_for_nothing() {
+        let problem = PairingProblem::from_card_counts(&[2, 2, 2, 2, 0]);
+        let pairings = [(0, 1), (1, 2), (2, 3), (3, 0)];
+        assert_eq!(min_fulfillment(&problem, &pairings), 0.5);
+    }
+
+    #[test]
+    fn greedy_start_is_accepted_without_single_ring() {
+        let problem = PairingProblem::from_card_counts(&[3; 5]);
+        let goal = Goal::Optimize {
+            objective: Objective::TotalWeight,
+            locked: &[],
+        };
+        let pairing_model = build_model(&problem, goal, &Alternatives::default());
+        assert!(pairing_model.add_start(&problem, &greedy_pairings(&problem)));
+    }
+}
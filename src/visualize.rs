@@ -0,0 +1,193 @@
+use anyhow::Result;
+use image::{ImageBuffer, Rgb, RgbImage};
+
+use crate::PairingSolution;
+
+/// Longest label drawn next to a row or above a column; longer names are truncated.
+const MAX_LABEL_CHARS: usize = 16;
+
+/// Visualize the solution matrix as a PNG image where each cell is a 9x9 square with 1px white borders.
+/// Rows are senders and columns are receivers, labelled with participant names.
+pub fn visualize_solution_matrix(solution: &PairingSolution, filename: &str) -> Result<()> {
+    let n = solution.participants.len();
+    let cell_size = 9;
+    let border_size = 1;
+    // Grid size: n cells of size cell_size + (n+1) borders of size border_size
+    let grid_size = n * cell_size + (n + 1) * border_size;
+
+    // Sender names are written left of each row, receiver names top-down above each column.
+    let labels: Vec<Vec<char>> = solution
+        .participants
+        .iter()
+        .map(|p| p.name.chars().take(MAX_LABEL_CHARS).collect())
+        .collect();
+    let max_label_len = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+    let label_width = max_label_len * (GLYPH_WIDTH + 1) + 2;
+    let label_height = max_label_len * (GLYPH_HEIGHT + 1) + 2;
+    let image_width = label_width + grid_size;
+    let image_height = label_height + grid_size;
+
+    // Create a new RGB image with white background (for borders)
+    let mut img: RgbImage = ImageBuffer::new(image_width as u32, image_height as u32);
+    let white = Rgb([255, 255, 255]);
+    let black = Rgb([0, 0, 0]);
+
+    // Fill the entire image with white (this creates the border effect)
+    for pixel in img.pixels_mut() {
+        *pixel = white;
+    }
+
+    for (i, label) in labels.iter().enumerate() {
+        let cell_offset = border_size + i * (cell_size + border_size);
+        // Row label, right-aligned against the grid and vertically centred in the row.
+        let mut x = label_width - 2 - label.len() * (GLYPH_WIDTH + 1);
+        let y = label_height + cell_offset + (cell_size - GLYPH_HEIGHT) / 2;
+        for &c in label {
+            draw_glyph(&mut img, c, x, y, black);
+            x += GLYPH_WIDTH + 1;
+        }
+        // Column label, one character per line, bottom-aligned against the grid.
+        let x = label_width + cell_offset + (cell_size - GLYPH_WIDTH) / 2;
+        let mut y = label_height - 2 - label.len() * (GLYPH_HEIGHT + 1);
+        for &c in label {
+            draw_glyph(&mut img, c, x, y, black);
+            y += GLYPH_HEIGHT + 1;
+        }
+    }
+
+    // Count how many pairings each row (sender) and column (receiver) has
+    let mut row_counts = vec![0; n];
+    let mut col_counts = vec![0; n];
+    for (i, j) in &solution.pairings {
+        row_counts[*i] += 1;
+        col_counts[*j] += 1;
+    }
+
+    // Find the maximum counts for normalization
+    let max_row_count = *row_counts.iter().max().unwrap_or(&1);
+    let max_col_count = *col_counts.iter().max().unwrap_or(&1);
+
+    // Define colors
+    let blue = Rgb([0, 100, 200]); // Has pairing
+
+    // Fill each cell
+    for (row, row_count) in row_counts.iter().enumerate() {
+        for (col, col_count) in col_counts.iter().enumerate() {
+            // Calculate cell position accounting for borders
+            let start_x = label_width + border_size + col * (cell_size + border_size);
+            let start_y = label_height + border_size + row * (cell_size + border_size);
+
+            if solution.has_pairing(row, col) {
+                // Fill entire cell with blue
+                for dy in 0..cell_size {
+                    for dx in 0..cell_size {
+                        let x = start_x + dx;
+                        let y = start_y + dy;
+                        if x < image_width && y < image_height {
+                            img.put_pixel(x as u32, y as u32, blue);
+                        }
+                    }
+                }
+            } else {
+                let color_scale = 128.0;
+                // Split cell diagonally into two triangles
+                // Calculate grey tones for row (bottom-left triangle) and column (top-right triangle)
+                let row_activity_ratio = *row_count as f32 / max_row_count as f32;
+                let row_grey_value = (256.0 - (row_activity_ratio * color_scale)) as u8; // 240 down to 200
+                let row_color = Rgb([row_grey_value, row_grey_value, row_grey_value]);
+
+                let col_activity_ratio = *col_count as f32 / max_col_count as f32;
+                let col_grey_value = (256.0 - (col_activity_ratio * color_scale)) as u8; // 240 down to 200
+                let col_color = Rgb([col_grey_value, col_grey_value, col_grey_value]);
+
+                // Fill the 9x9 square with diagonal split
+                for dy in 0..cell_size {
+                    for dx in 0..cell_size {
+                        let x = start_x + dx;
+                        let y = start_y + dy;
+                        if x < image_width && y < image_height {
+                            // Determine which triangle this pixel is in
+                            // Top-left to bottom-right diagonal: if dx >= dy, it's top-right triangle (column-based)
+                            // if dx < dy, it's bottom-left triangle (row-based)
+                            let color = if dx >= dy {
+                                col_color // Top-right triangle: column activity
+                            } else {
+                                row_color // Bottom-left triangle: row activity
+                            };
+                            img.put_pixel(x as u32, y as u32, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Save the image
+    img.save(filename)?;
+
+    Ok(())
+}
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// Draws `c` in a 3x5 pixel font with its top-left corner at `(x, y)`. Letters are drawn in
+/// upper case; characters without a glyph are left blank.
+fn draw_glyph(img: &mut RgbImage, c: char, x: usize, y: usize, color: Rgb<u8>) {
+    let Some(rows) = glyph(c.to_ascii_uppercase()) else {
+        return;
+    };
+    for (dy, bits) in rows.iter().enumerate() {
+        for dx in 0..GLYPH_WIDTH {
+            if bits & (0b100 >> dx) != 0 {
+                img.put_pixel((x + dx) as u32, (y + dy) as u32, color);
+            }
+        }
+    }
+}
+
+/// Rows of the glyph for `c`, top to bottom, with the leftmost pixel in the highest bit.
+fn glyph(c: char) -> Option<[u8; GLYPH_HEIGHT]> {
+    Some(match c {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => return None,
+    })
+}
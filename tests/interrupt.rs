@@ -0,0 +1,35 @@
+//! Interrupting a solve through SCIP. Interrupt requests are global, so this runs in a process
+//! of its own rather than next to the solves in `solve.rs`.
+
+use russcip::Status;
+use scip_talk::*;
+
+#[test]
+fn interrupted_solves_keep_the_best_pairing_found() {
+    // Weight the ring the greedy start does not use, so that SCIP reports a better pairing.
+    let problem = PairingProblem::from_card_counts(&[1; 3]);
+    let problem = greedy_pairings(&problem)
+        .into_iter()
+        .fold(problem, |problem, (i, j)| problem.pair_weight(j, i, 5.));
+
+    let solution = problem
+        .clone()
+        .on_progress(|_| request_interrupt())
+        .solve()
+        .unwrap();
+    // An interrupt during the last level may come too late to stop it.
+    assert!(
+        matches!(
+            solution.stats.status,
+            Status::UserInterrupt | Status::Optimal
+        ),
+        "{:?}",
+        solution.stats.status
+    );
+    for i in 0..3 {
+        assert_eq!(solution.sent_by(i).len(), solution.received_by(i).len());
+    }
+
+    // The next solve starts afresh.
+    assert!(problem.solve().unwrap().stats.is_optimal());
+}
@@ -0,0 +1,581 @@
+//! End-to-end solves through SCIP: a small instance for each kind of rule the model supports.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use russcip::Status;
+use scip_talk::*;
+
+/// Solves `problem` and checks the rules that every pairing keeps, whatever else is asked.
+fn solve(problem: &PairingProblem) -> PairingSolution {
+    let solution = problem.solve().unwrap();
+    for &(i, j) in &solution.pairings {
+        assert_ne!(i, j, "{} sends to themself", i);
+        assert!(!problem.is_forbidden(i, j), "forbidden pair {} -> {}", i, j);
+        assert!(
+            !solution.has_pairing(j, i),
+            "mutual exchange {} <-> {}",
+            i,
+            j
+        );
+    }
+    for (i, participant) in problem.participants().iter().enumerate() {
+        let sent = solution.sent_by(i).len() as u32;
+        let received = solution.received_by(i).len() as u32;
+        let (sends, receives) = (participant.send_range(), participant.receive_range());
+        assert!(
+            (sends.min..=sends.max).contains(&sent),
+            "{} sends {}",
+            i,
+            sent
+        );
+        assert!(
+            (receives.min..=receives.max).contains(&received),
+            "{} receives {}",
+            i,
+            received
+        );
+        if participant.is_balanced() {
+            assert_eq!(sent, received, "{} is unbalanced", i);
+        }
+    }
+    solution
+}
+
+#[test]
+fn rounding_heuristic_handles_pairs_that_presolve_removes() {
+    // P0 can only send to P1 and only receive from P2, so presolve merges P0 -> P1 with
+    // P2 -> P0. P3 must receive a card and can only send to P4, so P3 -> P4 is fixed.
+    let mut participants: Vec<Participant> = (0..8)
+        .map(|i| Participant::new(format!("P{}", i), 2))
+        .collect();
+    participants[3] = participants[3].clone().min_cards(MinCards::Absolute(1));
+    let mut problem = PairingProblem::from_participants(participants).rounding_heuristic();
+    for j in 2..8 {
+        problem = problem.forbid_pair(0, j);
+    }
+    for i in (1..8).filter(|&i| i != 2) {
+        problem = problem.forbid_pair(i, 0);
+    }
+    for j in (0..8).filter(|&j| j != 3 && j != 4) {
+        problem = problem.forbid_pair(3, j);
+    }
+
+    let solution = solve(&problem);
+    assert!(solution.has_pairing(3, 4));
+    assert_eq!(solution.has_pairing(0, 1), solution.has_pairing(2, 0));
+}
+
+#[test]
+fn repeats_are_forbidden_by_default() {
+    let problem = PairingProblem::from_card_counts(&[1; 4])
+        .past_pairing(0, 1, 1)
+        .past_pairing(1, 2, 1)
+        .past_pairing(2, 3, 1)
+        .past_pairing(3, 0, 1);
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 4);
+    assert!(problem.repeated_pairs(&solution).is_empty());
+    let cost = problem.repeat_ban_cost(&solution).unwrap().unwrap();
+    assert_eq!(cost.cards_lost, 0);
+}
+
+#[test]
+fn repeat_ban_cost_counts_the_cards_it_loses() {
+    // Every ring of three repeats one of these, so the ban leaves nobody a card.
+    let problem = PairingProblem::from_card_counts(&[1; 3])
+        .past_pairing(0, 1, 1)
+        .past_pairing(1, 0, 2);
+    let solution = solve(&problem);
+    assert!(solution.pairings.is_empty());
+    let cost = problem.repeat_ban_cost(&solution).unwrap().unwrap();
+    assert_eq!(
+        cost,
+        RepeatBanCost {
+            cards_lost: 3,
+            repeats_needed: 1
+        }
+    );
+}
+
+#[test]
+fn penalized_repeats_prefer_the_oldest() {
+    let problem = PairingProblem::from_card_counts(&[1; 3])
+        .past_pairing(0, 1, 1)
+        .past_pairing(1, 0, 2)
+        .handle_repeats(HistoryMode::Penalize {
+            penalty: DEFAULT_REPEAT_PENALTY,
+            decay: DEFAULT_REPEAT_DECAY,
+        });
+    let solution = solve(&problem);
+    assert_eq!(problem.repeated_pairs(&solution), vec![(1, 0)]);
+    assert_eq!(problem.repeat_ban_cost(&solution).unwrap(), None);
+}
+
+#[test]
+fn cards_from_abroad_need_a_country() {
+    let problem = PairingProblem::from_participants(vec![
+        Participant::new("A", 1).country("NL"),
+        Participant::new("B", 1).country("DE"),
+        Participant::new("C", 1).international_mix(InternationalMix::AtLeast(1)),
+    ]);
+    let error = problem.solve().unwrap_err().to_string();
+    assert!(error.contains("without a country: C"), "{}", error);
+}
+
+#[test]
+fn everyone_sends_and_receives_what_they_asked_for() {
+    let problem = PairingProblem::from_card_counts(&[2; 5]);
+    let solution = solve(&problem);
+    assert!(solution.stats.is_optimal());
+    for i in 0..5 {
+        assert_eq!(solution.sent_by(i).len(), 2);
+        assert_eq!(solution.received_by(i).len(), 2);
+    }
+}
+
+#[test]
+fn exclusions_and_households_are_kept_apart() {
+    let problem = PairingProblem::from_participants(vec![
+        Participant::new("A", 1).group("home"),
+        Participant::new("B", 1).group("home"),
+        Participant::new("C", 1),
+        Participant::new("D", 1),
+        Participant::new("E", 1),
+        Participant::new("F", 1),
+    ])
+    .exclusion_group(vec![2, 3])
+    .forbid_pair(4, 5);
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 6);
+    assert!(problem.violated_exclusions(&solution).is_empty());
+}
+
+#[test]
+fn required_pairs_are_used() {
+    let problem = PairingProblem::from_card_counts(&[1; 4])
+        .require_pair(0, 2)
+        .require_pair(2, 1);
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 4);
+    assert!(problem.missing_required_pairs(&solution).is_empty());
+}
+
+#[test]
+fn conflicting_required_pairs_are_reported_before_solving() {
+    let problem = PairingProblem::from_card_counts(&[1; 4])
+        .require_pair(0, 1)
+        .require_pair(1, 0);
+    let error = problem.solve().unwrap_err().to_string();
+    assert!(error.contains("Required pairs conflict"), "{}", error);
+}
+
+#[test]
+fn required_pairs_are_blamed_when_they_cause_infeasibility() {
+    // The required pairs close a cycle of three, which is too short; without them, a ring of
+    // four is fine.
+    let problem = PairingProblem::from_card_counts(&[1; 4])
+        .forbid_short_cycles(4)
+        .require_pair(0, 1)
+        .require_pair(1, 2)
+        .require_pair(2, 0);
+    let error = problem.solve().unwrap_err().to_string();
+    assert!(
+        error.contains("No valid pairing contains all required pairs"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn minimums_hold_when_they_cost_weight() {
+    // Every card to P4 costs weight, so optimizing weight alone leaves them out.
+    let mut problem =
+        PairingProblem::from_card_counts(&[1; 4]).optimize(ObjectiveMode::Lexicographic(vec![
+            ObjectiveLevel::new(Objective::TotalWeight),
+        ]));
+    for i in 0..3 {
+        problem = problem.pair_weight(i, 3, -5.);
+    }
+    assert!(solve(&problem).received_by(3).is_empty());
+
+    let problem = problem.min_cards(MinCards::Absolute(1));
+    assert_eq!(solve(&problem).received_by(3).len(), 1);
+}
+
+#[test]
+fn minimums_that_cannot_be_met_are_explained() {
+    // Nobody may send to C.
+    let problem = PairingProblem::from_participants(vec![
+        Participant::new("A", 1),
+        Participant::new("B", 1),
+        Participant::new("C", 1).min_cards(MinCards::Absolute(1)),
+        Participant::new("D", 1),
+    ])
+    .forbid_pair(0, 2)
+    .forbid_pair(1, 2)
+    .forbid_pair(3, 2);
+    let error = problem.solve().unwrap_err().to_string();
+    assert!(
+        error.contains("C with 0 of their minimum 1 cards"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn fairness_shares_out_the_shortfall() {
+    // One card can reach Y, but X weighs more for S2; Y gets nothing unless fairness comes first.
+    let problem = PairingProblem::from_participants(vec![
+        Participant::new("S1", 1).role(Role::SendOnly),
+        Participant::new("S2", 1).role(Role::SendOnly),
+        Participant::new("X", 2)
+            .sends(CardRange::up_to(0))
+            .balanced(false),
+        Participant::new("Y", 1)
+            .sends(CardRange::up_to(0))
+            .balanced(false),
+    ])
+    .forbid_pair(0, 3)
+    .pair_weight(1, 2, 3.);
+    let solution = solve(&problem);
+    assert!(solution.received_by(3).is_empty());
+
+    let problem = problem.optimize(ObjectiveMode::MaxMinFairness);
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 2);
+    assert_eq!(solution.received_by(3), vec![1]);
+    let worst = solution
+        .fulfillment_ratios()
+        .into_iter()
+        .flatten()
+        .fold(1., f64::min);
+    assert_eq!(worst, 0.5);
+}
+
+#[test]
+fn lexicographic_levels_keep_earlier_optima_within_tolerance() {
+    // A ring of four sends the most cards but uses only two of the preferred pairs; the ring
+    // of three that uses all three sends one card fewer.
+    let problem = PairingProblem::from_card_counts(&[1; 4])
+        .pair_preference(0, 1, 1.)
+        .pair_preference(1, 2, 1.)
+        .pair_preference(2, 0, 1.);
+    let lexicographic = |cards: ObjectiveLevel| {
+        problem.clone().optimize(ObjectiveMode::Lexicographic(vec![
+            cards,
+            ObjectiveLevel::new(Objective::Preference),
+        ]))
+    };
+
+    let solution = solve(&lexicographic(ObjectiveLevel::new(Objective::TotalCards)));
+    assert_eq!(solution.pairings.len(), 4);
+    let values: Vec<f64> = solution
+        .stats
+        .levels
+        .iter()
+        .map(|level| level.value)
+        .collect();
+    assert_eq!(values, vec![4., 2.]);
+
+    let solution = solve(&lexicographic(
+        ObjectiveLevel::new(Objective::TotalCards).tolerance(1.),
+    ));
+    assert_eq!(solution.pairings.len(), 3);
+    assert_eq!(solution.stats.levels[1].value, 3.);
+}
+
+#[test]
+fn seeds_reshuffle_reproducibly_without_losing_cards() {
+    let seeded = |seed| solve(&PairingProblem::from_card_counts(&[1; 6]).seed(seed));
+    let solutions: Vec<PairingSolution> = (0..8).map(seeded).collect();
+    for solution in &solutions {
+        assert_eq!(solution.pairings.len(), 6);
+    }
+    assert_eq!(seeded(3).pairings, solutions[3].pairings);
+    // Many rings send every card, so a handful of seeds should find more than one of them.
+    assert!(
+        solutions
+            .iter()
+            .any(|solution| solution.distance(&solutions[0]) > 0)
+    );
+}
+
+#[test]
+fn distinct_pairings_differ_by_the_minimum_and_run_out() {
+    // Three people have two rings, one each way, and the empty pairing, which is three pairs
+    // away from either.
+    let problem = PairingProblem::from_card_counts(&[1; 3]);
+    let solutions = problem.solve_distinct(5, 1).unwrap();
+    let cards: Vec<usize> = solutions.iter().map(|s| s.pairings.len()).collect();
+    assert_eq!(cards, vec![3, 3, 0]);
+    assert_eq!(solutions[0].distance(&solutions[1]), 6);
+
+    let solutions = problem.solve_distinct(5, 4).unwrap();
+    assert_eq!(solutions.len(), 2);
+}
+
+#[test]
+fn repair_closes_the_gap_a_dropout_leaves() {
+    let previous: Vec<(String, String)> =
+        [("A", "B"), ("B", "C"), ("C", "D"), ("D", "E"), ("E", "A")]
+            .iter()
+            .map(|&(s, r)| (s.to_string(), r.to_string()))
+            .collect();
+    // D dropped out.
+    let problem = PairingProblem::from_participants(
+        ["A", "B", "C", "E"]
+            .iter()
+            .map(|name| Participant::new(*name, 1))
+            .collect(),
+    )
+    .previous_solution(&previous);
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 4);
+    assert!(solution.has_pairing(2, 3));
+    let changed: Vec<String> = problem
+        .partner_changes(&solution)
+        .into_iter()
+        .map(|change| change.participant)
+        .collect();
+    assert_eq!(changed, vec!["C", "E"]);
+}
+
+#[test]
+fn appending_fits_newcomers_around_the_existing_pairs() {
+    // A has a card to spare, so the newcomers D and E can join through them.
+    let ring = [(0, 1), (1, 2), (2, 0)];
+    let problem = PairingProblem::from_card_counts(&[2, 1, 1, 1, 1]).append_to(&ring);
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 7);
+    assert!(problem.changed_existing_pairs(&solution).is_empty());
+
+    // A lone newcomer only fits in if one existing pair may be broken up.
+    let problem = PairingProblem::from_card_counts(&[1; 4]).append_to(&ring);
+    assert!(solve(&problem).received_by(3).is_empty());
+    let problem = problem.allow_changes(1);
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 4);
+    assert_eq!(problem.changed_existing_pairs(&solution).len(), 1);
+}
+
+#[test]
+fn preferences_pick_among_rings_that_send_every_card() {
+    let ring = [(0, 2), (2, 1), (1, 3), (3, 0)];
+    let mut problem = PairingProblem::from_card_counts(&[1; 4]);
+    for &(i, j) in &ring {
+        problem = problem.pair_preference(i, j, 1.);
+    }
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 4);
+    assert!(ring.iter().all(|&(i, j)| solution.has_pairing(i, j)));
+    assert!(
+        problem
+            .preference_satisfaction(&solution)
+            .iter()
+            .all(|&score| score == Some(1.))
+    );
+}
+
+/// Six people with one card each, who would rather send around two rings of three.
+fn two_triangles() -> PairingProblem {
+    let triangles = [(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)];
+    triangles.iter().fold(
+        PairingProblem::from_card_counts(&[1; 6]),
+        |problem, &(i, j)| problem.pair_weight(i, j, 5.),
+    )
+}
+
+#[test]
+fn short_cycles_are_banned() {
+    assert_eq!(solve(&two_triangles()).shortest_cycle(), Some(3));
+
+    let solution = solve(&two_triangles().forbid_short_cycles(4));
+    assert_eq!(solution.pairings.len(), 6);
+    assert!(solution.shortest_cycle().unwrap() >= 4);
+}
+
+#[test]
+fn single_ring_joins_everyone_into_one() {
+    assert_eq!(solve(&two_triangles()).components().len(), 2);
+
+    let solution = solve(&two_triangles().single_ring());
+    let components = solution.components();
+    assert_eq!(components.len(), 1);
+    assert_eq!(components[0].len(), 6);
+    for i in 0..6 {
+        assert_eq!(solution.sent_by(i).len(), 1);
+    }
+}
+
+#[test]
+fn strong_connectivity_joins_the_rings_but_leaves_out_one_way_participants() {
+    let mut participants: Vec<Participant> = (0..6)
+        .map(|i| Participant::new(format!("P{}", i), 1))
+        .collect();
+    participants.push(Participant::new("S", 1).role(Role::SendOnly));
+    participants.push(Participant::new("R", 1).role(Role::ReceiveOnly));
+    let triangles = [(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)];
+    let problem = triangles
+        .iter()
+        .fold(
+            PairingProblem::from_participants(participants),
+            |problem, &(i, j)| problem.pair_weight(i, j, 5.),
+        )
+        .require_strong_connectivity();
+
+    let solution = solve(&problem);
+    assert!(solution.has_pairing(6, 7));
+    let ring = solution
+        .components()
+        .into_iter()
+        .find(|members| members.contains(&0))
+        .unwrap();
+    assert_eq!(ring.len(), 6);
+}
+
+#[test]
+fn send_and_receive_ranges_hold_for_unbalanced_participants() {
+    let problem = PairingProblem::from_participants(vec![
+        Participant::new("A", 2)
+            .sends(CardRange::new(2, 2))
+            .receives(CardRange::up_to(1)),
+        Participant::new("B", 2)
+            .sends(CardRange::up_to(1))
+            .receives(CardRange::new(2, 2)),
+        Participant::new("C", 1),
+        Participant::new("D", 1),
+    ]);
+    let solution = solve(&problem);
+    assert_eq!(solution.sent_by(0).len(), 2);
+    assert_eq!(solution.received_by(1).len(), 2);
+}
+
+#[test]
+fn volunteers_only_send_and_residents_only_receive() {
+    let problem = PairingProblem::from_participants(vec![
+        Participant::new("Volunteer", 2).role(Role::SendOnly),
+        Participant::new("Resident 1", 1).role(Role::ReceiveOnly),
+        Participant::new("Resident 2", 1).role(Role::ReceiveOnly),
+        Participant::new("A", 1),
+        Participant::new("B", 1),
+        Participant::new("C", 1),
+    ]);
+    let solution = solve(&problem);
+    assert!(solution.received_by(0).is_empty());
+    for resident in problem.receive_only() {
+        assert!(solution.sent_by(resident).is_empty());
+        assert_eq!(solution.received_by(resident).len(), 1);
+    }
+}
+
+#[test]
+fn shipping_stays_domestic_where_it_can() {
+    let people = |countries: &[&str]| {
+        countries
+            .iter()
+            .enumerate()
+            .map(|(i, country)| Participant::new(format!("P{}", i), 1).country(*country))
+            .collect::<Vec<_>>()
+    };
+    let problem = PairingProblem::from_participants(people(&["NL", "NL", "NL", "DE", "DE", "DE"]))
+        .minimize_shipping();
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 6);
+    assert_eq!(problem.total_shipping_cost(&solution), 6.);
+
+    // Two people per country cannot keep a ring domestic, but P0 can still send at home.
+    let mut participants = people(&["NL", "NL", "DE", "DE"]);
+    participants[0] = participants[0].clone().max_international(0);
+    let problem = PairingProblem::from_participants(participants);
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 4);
+    assert_eq!(solution.sent_by(0), vec![1]);
+}
+
+#[test]
+fn international_mix_is_respected() {
+    let problem = PairingProblem::from_participants(vec![
+        Participant::new("A", 1)
+            .country("NL")
+            .international_mix(InternationalMix::DomesticOnly),
+        Participant::new("B", 1).country("NL"),
+        Participant::new("C", 1)
+            .country("NL")
+            .international_mix(InternationalMix::AtLeast(1)),
+        Participant::new("D", 1).country("DE"),
+        Participant::new("E", 1).country("DE"),
+        Participant::new("F", 1).country("DE"),
+    ])
+    .minimize_shipping();
+    let solution = solve(&problem);
+    assert_eq!(solution.pairings.len(), 6);
+    let from_abroad = |receiver: usize| {
+        solution
+            .received_by(receiver)
+            .into_iter()
+            .filter(|&sender| problem.is_international(sender, receiver))
+            .count()
+    };
+    assert_eq!(solution.received_by(0).len(), 1);
+    assert_eq!(from_abroad(0), 0);
+    assert_eq!(from_abroad(2), 1);
+}
+
+#[test]
+fn limits_return_the_best_pairing_found() {
+    let mut problem = PairingProblem::from_card_counts(&[2; 8]);
+    for i in 0..8 {
+        problem = problem.pair_weight(i, (i * 3 + 1) % 8, 2.);
+    }
+
+    for problem in [problem.clone().node_limit(1), problem.time_limit(0.)] {
+        let solution = solve(&problem);
+        let stats = &solution.stats;
+        assert!(
+            matches!(
+                stats.status,
+                Status::Optimal | Status::NodeLimit | Status::TimeLimit
+            ),
+            "{:?}",
+            stats.status
+        );
+        assert!(!solution.pairings.is_empty());
+        assert!(stats.objective <= stats.dual_bound + 1e-6);
+        assert!(stats.gap >= 0.);
+    }
+}
+
+#[test]
+fn progress_reports_each_better_pairing() {
+    // Weight the ring the greedy start does not use, so that SCIP has to find a better one.
+    let problem = PairingProblem::from_card_counts(&[1; 3]);
+    let problem = greedy_pairings(&problem)
+        .into_iter()
+        .fold(problem, |problem, (i, j)| problem.pair_weight(j, i, 5.));
+    let updates = Rc::new(RefCell::new(Vec::new()));
+    let problem = problem.on_progress({
+        let updates = updates.clone();
+        move |progress| updates.borrow_mut().push(progress.clone())
+    });
+
+    let solution = solve(&problem);
+    let updates = updates.borrow();
+    assert!(!updates.is_empty());
+    for progress in updates.iter() {
+        let level = &solution.stats.levels[progress.level];
+        assert_eq!(progress.objective, level.objective);
+        assert!(progress.value <= level.value + 1e-6);
+        assert!(progress.dual_bound >= progress.value - 1e-6);
+    }
+    assert!(updates.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+    assert!(updates.iter().any(|p| p.pairings == solution.pairings));
+}
+
+#[test]
+fn greedy_start_is_scored_against_the_optimum() {
+    let problem = PairingProblem::from_card_counts(&[2, 2, 1, 1, 1, 1]);
+    let solution = solve(&problem);
+    let greedy = solution.stats.greedy_objective.unwrap();
+    assert!(greedy <= solution.stats.objective + 1e-6);
+}